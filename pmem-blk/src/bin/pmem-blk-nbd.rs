@@ -0,0 +1,100 @@
+//! Serves a block memory pool as a Network Block Device
+//!
+//! ```text
+//! pmem-blk-nbd <pool> --unix <socket>
+//! pmem-blk-nbd <pool> --tcp <address>
+//! ```
+//!
+//! The pool can then be attached with `nbd-client -unix <socket> /dev/nbd0`
+//! or `nbd-client 127.0.0.1 10809 /dev/nbd0`.
+
+extern crate pmem_blk;
+
+use ::std::env;
+use ::std::fs;
+use ::std::io;
+use ::std::net::TcpListener;
+use ::std::os::unix::net::UnixListener;
+use ::std::path::{Path, PathBuf};
+use ::std::process;
+use ::std::sync::Arc;
+use ::std::thread;
+
+use ::pmem_blk::{nbd, BlkPool};
+
+const USAGE: &str = "Usage: pmem-blk-nbd <pool> (--unix <socket> | --tcp <address>)";
+
+enum Listen {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+fn parse_args() -> Option<(PathBuf, Listen)> {
+    let mut args = env::args_os().skip(1);
+    let (pool, kind, target) = (args.next()?, args.next()?, args.next()?);
+    if args.next().is_some() {
+        return None;
+    }
+    let listen = match kind.to_str()? {
+        "--unix" => Listen::Unix(target.into()),
+        "--tcp" => Listen::Tcp(target.into_string().ok()?),
+        _ => return None,
+    };
+    Some((pool.into(), listen))
+}
+
+fn run(pool: &Path, listen: Listen) -> io::Result<()> {
+    let pool = Arc::new(BlkPool::open_no_size(pool)?);
+
+    match listen {
+        Listen::Unix(path) => {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            let listener = UnixListener::bind(&path)?;
+            println!("Serving {} blocks of {} bytes on {}",
+                     pool.capacity(),
+                     pool.block_size(),
+                     path.display());
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let pool = pool.clone();
+                thread::spawn(move || {
+                    if let Err(err) = nbd::serve(&pool, stream) {
+                        eprintln!("Connection closed: {}", err);
+                    }
+                });
+            }
+        }
+        Listen::Tcp(addr) => {
+            let listener = TcpListener::bind(&addr)?;
+            println!("Serving {} blocks of {} bytes on {}", pool.capacity(), pool.block_size(), addr);
+            for stream in listener.incoming() {
+                let stream = stream?;
+                stream.set_nodelay(true)?;
+                let pool = pool.clone();
+                thread::spawn(move || {
+                    if let Err(err) = nbd::serve(&pool, stream) {
+                        eprintln!("Connection closed: {}", err);
+                    }
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let (pool, listen) = match parse_args() {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(&pool, listen) {
+        eprintln!("pmem-blk-nbd: {}", err);
+        process::exit(1);
+    }
+}
@@ -1,8 +1,9 @@
-use ::std::ffi::{CString, CStr};
-use ::std::path::Path;
+use ::std::ffi::CStr;
+use ::std::path::{Path, PathBuf};
 use ::std::io;
 
 use ::libc::{size_t, mode_t};
+use ::pmem::error::{Error, path_to_cstring};
 use ::pmemblk_sys::{self as ffi, PMEMblkpool};
 
 pub use ::pmemblk_sys::PMEMBLK_MIN_POOL as MIN_POOLSIZE;
@@ -18,30 +19,40 @@ fn errormsg() -> Option<String> {
     }
 }
 
+/// Captures the error of the failed call `operation` on the pool at `path`
+fn error(operation: &'static str, path: &Path) -> Error {
+    Error::last_os_error(operation).with_message(errormsg()).with_path(path)
+}
+
 pub struct BlkPool {
     inner: *mut PMEMblkpool,
+    path: PathBuf,
 }
 
+// libpmemblk is thread-safe, all the operations on a pool can be called concurrently.
+unsafe impl Send for BlkPool {}
+unsafe impl Sync for BlkPool {}
 
 impl BlkPool {
     /// Opens an existent memory pool with an _unknown_ block size
     ///
     /// Use `block_size()` to query the block size of the opened memory pool.
-    pub fn open_no_size<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> { BlkPool::open(path, 0) }
+    pub fn open_no_size<P: AsRef<Path>>(path: P) -> Result<Self, Error> { BlkPool::open(path, 0) }
 
     /// Opens an existent memory pool
     ///
     /// If the `blksize` provided is non-zero, we will verify the given block size matches
     /// the block size used when the pool was created.
-    pub fn open<P: AsRef<Path>>(path: P, blksize: usize) -> Result<Self, io::Error> {
-        let path = CString::new(path.as_ref().to_str().unwrap()).unwrap();
+    pub fn open<P: AsRef<Path>>(path: P, blksize: usize) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let cpath = path_to_cstring("pmemblk_open", path)?;
 
-        let objpool = unsafe { ffi::pmemblk_open(path.as_ptr(), blksize as size_t) };
+        let objpool = unsafe { ffi::pmemblk_open(cpath.as_ptr(), blksize as size_t) };
 
         if objpool.is_null() {
-            Err(io::Error::last_os_error())
+            Err(error("pmemblk_open", path))
         } else {
-            Ok(BlkPool { inner: objpool })
+            Ok(BlkPool { inner: objpool, path: path.to_path_buf() })
         }
     }
 
@@ -54,52 +65,49 @@ impl BlkPool {
     ///
     /// Given the specifics of the implementation, the number of available blocks for the user cannot be less than 256.
     /// This translates to at least 512 internal blocks.
-    pub fn create<P: AsRef<Path>>(path: P, blksize: usize, poolsize: usize) -> Result<Self, io::Error> {
-        let path = path.as_ref().to_str().unwrap();
-        let path = CString::new(path).unwrap();
+    pub fn create<P: AsRef<Path>>(path: P, blksize: usize, poolsize: usize) -> Result<Self, Error> {
+        BlkPool::create_mode(path.as_ref(), blksize, poolsize, 0o666)
+    }
 
-        let mode = 0o666;
+    fn create_mode(path: &Path, blksize: usize, poolsize: usize, mode: u32) -> Result<Self, Error> {
+        let cpath = path_to_cstring("pmemblk_create", path)?;
 
         let objpool = unsafe {
-            ffi::pmemblk_create(path.as_ptr(), blksize as size_t, poolsize as size_t, mode as mode_t)
+            ffi::pmemblk_create(cpath.as_ptr(), blksize as size_t, poolsize as size_t, mode as mode_t)
         };
 
         if objpool.is_null() {
-            let err = io::Error::last_os_error();
-            if err.kind() == io::ErrorKind::InvalidInput {
-                if let Some(msg) = errormsg() {
-                    return Err(io::Error::new(io::ErrorKind::Other, msg));
-                }
-            }
-            Err(err)
+            Err(error("pmemblk_create", path))
         } else {
-            Ok(BlkPool { inner: objpool })
+            Ok(BlkPool { inner: objpool, path: path.to_path_buf() })
         }
     }
 
+    /// Options to open or create a pool, see `BlkPoolOptions`
+    pub fn options() -> BlkPoolOptions { BlkPoolOptions::new() }
+
     /// The block size for this pool
     pub fn block_size(&self) -> usize { unsafe { ffi::pmemblk_bsize(self.inner) as usize } }
 
+    /// The path of the pool file
+    pub fn path(&self) -> &Path { &self.path }
+
     /// The capacity of the pool in number of blocks
     pub fn capacity(&self) -> usize { unsafe { ffi::pmemblk_nblock(self.inner) as usize } }
 
     /// Reads block number `blockno` from the memory pool into `buf`
     ///
     /// Reading a block that has never been written will return a block of zeroes.
-    pub fn read(&self, buf: &mut [u8], blockno: i64) -> Result<(),io::Error> {
+    /// Fails with `ErrorKind::InvalidInput` if `buf` is smaller than a block.
+    pub fn read(&self, buf: &mut [u8], blockno: i64) -> Result<(), Error> {
+        self.check_buf("pmemblk_read", buf)?;
         let r = unsafe {
             ffi::pmemblk_read(self.inner, buf.as_ptr() as *mut _, blockno)
         };
         if r == 0 {
             Ok(())
         } else {
-            let err = io::Error::last_os_error();
-            if err.kind() == io::ErrorKind::InvalidInput {
-                if let Some(msg) = errormsg() {
-                    return Err(io::Error::new(io::ErrorKind::Other, msg));
-                }
-            }
-            Err(err)
+            Err(error("pmemblk_read", &self.path))
         }
     }
 
@@ -109,49 +117,165 @@ impl BlkPool {
     /// In addition, the write cannot be torn by program failure or system crash;
     /// on recovery the block is guaranteed to contain either the old data or the new data
     /// never a mixture of both.
-    pub fn write(&self, buf: &[u8], blockno: i64) -> Result<(),io::Error> {
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if `buf` is smaller than a block.
+    pub fn write(&self, buf: &[u8], blockno: i64) -> Result<(), Error> {
+        self.check_buf("pmemblk_write", buf)?;
         let r = unsafe {
             ffi::pmemblk_write(self.inner, buf.as_ptr() as *const _, blockno)
         };
         if r == 0 {
             Ok(())
         } else {
-            let err = io::Error::last_os_error();
-            if err.kind() == io::ErrorKind::InvalidInput {
-                if let Some(msg) = errormsg() {
-                    return Err(io::Error::new(io::ErrorKind::Other, msg));
-                }
-            }
-            Err(err)
+            Err(error("pmemblk_write", &self.path))
+        }
+    }
+
+    /// The library reads or writes a whole block from `buf`
+    fn check_buf(&self, operation: &'static str, buf: &[u8]) -> Result<(), Error> {
+        if buf.len() < self.block_size() {
+            return Err(Error::new(io::ErrorKind::InvalidInput,
+                                  operation,
+                                  format!("Buffer of {} bytes is smaller than the block size {}",
+                                          buf.len(),
+                                          self.block_size()))
+                .with_path(&self.path));
+        }
+        Ok(())
+    }
+
+    /// Writes zeros to block number `blockno` in the memory pool
+    ///
+    /// Like `write()` this is **atomic**, and it also clears the error state set by `set_error()`.
+    pub fn set_zero(&self, blockno: i64) -> Result<(), Error> {
+        let r = unsafe { ffi::pmemblk_set_zero(self.inner, blockno) };
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(error("pmemblk_set_zero", &self.path))
+        }
+    }
+
+    /// Marks block number `blockno` in the memory pool as being in an error state
+    ///
+    /// Reading a block in the error state will fail with `EIO` until the block is written again.
+    /// This is meant to be used when a block is known to be corrupt, for example after an integrity check fails.
+    pub fn set_error(&self, blockno: i64) -> Result<(), Error> {
+        let r = unsafe { ffi::pmemblk_set_error(self.inner, blockno) };
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(error("pmemblk_set_error", &self.path))
         }
     }
 
     /// Check consistency of the memory pool
-    pub fn check<P: AsRef<Path>>(path: P, blksize: usize) -> Result<bool, io::Error> {
-        let path = path.as_ref().to_str().unwrap();
-        let path = CString::new(path).unwrap();
+    pub fn check<P: AsRef<Path>>(path: P, blksize: usize) -> Result<bool, Error> {
+        let path = path.as_ref();
+        let cpath = path_to_cstring("pmemblk_check", path)?;
 
-        let r = unsafe { ffi::pmemblk_check(path.as_ptr(), blksize as size_t) };
+        let r = unsafe { ffi::pmemblk_check(cpath.as_ptr(), blksize as size_t) };
         match r {
             1 => Ok(true),
             0 => Ok(false),
-            -1 => {
-                let err = io::Error::last_os_error();
-                if err.kind() == io::ErrorKind::InvalidInput {
-                    if let Some(msg) = errormsg() {
-                        return Err(io::Error::new(io::ErrorKind::Other, msg));
+            -1 => Err(error("pmemblk_check", path)),
+            r => {
+                Err(Error::new(io::ErrorKind::Other,
+                               "pmemblk_check",
+                               format!("Invalid return value, expected 1, 0 or -1 but received {}", r))
+                        .with_path(path))
+            }
+        }
+    }
+}
+
+/// Options to open or create a `BlkPool`, in the style of `std::fs::OpenOptions`
+///
+/// ```no_run
+/// # use pmem_blk::BlkPoolOptions;
+/// let mut options = BlkPoolOptions::new();
+/// options.create(true).block_size(4096).size(64 * 1024 * 1024).mode(0o600);
+/// let pool = options.open("/mnt/pmem/blocks").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlkPoolOptions {
+    block_size: usize,
+    size: usize,
+    mode: u32,
+    create: bool,
+    create_new: bool,
+}
+
+impl BlkPoolOptions {
+    /// Options to open an existing pool, whatever its block size
+    pub fn new() -> Self { BlkPoolOptions { block_size: 0, size: 0, mode: 0o666, create: false, create_new: false } }
+
+    /// The block size, required to create a pool
+    ///
+    /// When opening an existing pool, a non-zero block size must match the one of the pool.
+    pub fn block_size(&mut self, block_size: usize) -> &mut Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// The size of the pool to create, see `BlkPool::create()`
+    ///
+    /// Required with `create()` or `create_new()`.
+    pub fn size(&mut self, size: usize) -> &mut Self {
+        self.size = size;
+        self
+    }
+
+    /// The permissions of a created pool file, `0o666` by default, the process umask applies
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Creates the pool if it does not exist, opens it otherwise
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Creates the pool, failing if the file already exists
+    ///
+    /// Takes precedence over `create()`.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Opens the pool at `path` with these options
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<BlkPool, Error> {
+        let path = path.as_ref();
+        // with a size of 0 the library formats an existing file in place instead of failing
+        if (self.create || self.create_new) && self.size == 0 {
+            return Err(Error::new(io::ErrorKind::InvalidInput, "pmemblk_create", "The size of the pool to create is 0")
+                .with_path(path));
+        }
+        if self.create_new {
+            return BlkPool::create_mode(path, self.block_size, self.size, self.mode);
+        }
+        match BlkPool::open(path, self.block_size) {
+            Err(ref err) if self.create && err.kind() == io::ErrorKind::NotFound => {
+                match BlkPool::create_mode(path, self.block_size, self.size, self.mode) {
+                    // created by someone else in the meantime
+                    Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                        BlkPool::open(path, self.block_size)
                     }
+                    r => r,
                 }
-                Err(err)
-            }
-            r => {
-                Err(io::Error::new(io::ErrorKind::Other,
-                                   format!("Invalid return value, expected 1, 0 or -1 but received {}", r)))
             }
+            r => r,
         }
     }
 }
 
+impl Default for BlkPoolOptions {
+    fn default() -> Self { BlkPoolOptions::new() }
+}
+
 impl Drop for BlkPool {
     fn drop(&mut self) {
         unsafe {
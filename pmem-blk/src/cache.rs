@@ -0,0 +1,199 @@
+//! DRAM cache for block memory pools
+//!
+//! Every `BlkPool::read` copies the block out of persistent memory.
+//! A `CachedPool` keeps the most recently used blocks in DRAM so hot blocks are served without touching the pool.
+
+use ::std::collections::{BTreeMap, HashMap};
+use ::std::io;
+use ::std::sync::Mutex;
+
+use blkpool::BlkPool;
+
+/// Hit and miss counters of a `CachedPool`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads served from the cache
+    pub hits: u64,
+    /// Reads that had to go to the pool
+    pub misses: u64,
+}
+
+struct CacheState {
+    /// Cached blocks and the tick they were last used at
+    blocks: HashMap<i64, (Vec<u8>, u64)>,
+    /// Blocks ordered by last use, the first entry is the least recently used
+    lru: BTreeMap<u64, i64>,
+    tick: u64,
+    /// Bumped on every write and invalidation, used to discard reads that raced with them
+    epoch: u64,
+    stats: CacheStats,
+}
+
+impl CacheState {
+    fn touch(&mut self, blockno: i64) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.blocks.get_mut(&blockno) {
+            self.lru.remove(&entry.1);
+            entry.1 = tick;
+            self.lru.insert(tick, blockno);
+        }
+    }
+
+    fn insert(&mut self, blockno: i64, data: Vec<u8>, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        self.remove(blockno);
+        while self.blocks.len() >= capacity {
+            let (&tick, &victim) = self.lru.iter().next().unwrap();
+            self.lru.remove(&tick);
+            self.blocks.remove(&victim);
+        }
+        self.tick += 1;
+        self.blocks.insert(blockno, (data, self.tick));
+        self.lru.insert(self.tick, blockno);
+    }
+
+    fn remove(&mut self, blockno: i64) {
+        if let Some((_, tick)) = self.blocks.remove(&blockno) {
+            self.lru.remove(&tick);
+        }
+    }
+}
+
+/// Block memory pool with a write-through LRU cache in DRAM
+///
+/// Writes go straight to the pool and only update the cache once they succeed,
+/// so the atomicity guarantees of `BlkPool::write` are preserved.
+pub struct CachedPool {
+    pool: BlkPool,
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl CachedPool {
+    /// Wraps `pool` with a cache holding up to `capacity` blocks
+    ///
+    /// A `capacity` of zero disables caching.
+    pub fn new(pool: BlkPool, capacity: usize) -> Self {
+        let state = CacheState {
+            blocks: HashMap::with_capacity(capacity),
+            lru: BTreeMap::new(),
+            tick: 0,
+            epoch: 0,
+            stats: CacheStats::default(),
+        };
+        CachedPool { pool, capacity, state: Mutex::new(state) }
+    }
+
+    /// The block size for this pool
+    pub fn block_size(&self) -> usize { self.pool.block_size() }
+
+    /// The capacity of the pool in number of blocks
+    pub fn capacity(&self) -> usize { self.pool.capacity() }
+
+    /// The maximum number of blocks kept in the cache
+    pub fn cache_capacity(&self) -> usize { self.capacity }
+
+    /// The number of blocks currently in the cache
+    pub fn cached(&self) -> usize { self.state.lock().unwrap().blocks.len() }
+
+    /// The underlying block memory pool
+    ///
+    /// Writing to it directly leaves the cache stale, use `invalidate()` afterwards.
+    pub fn get_ref(&self) -> &BlkPool { &self.pool }
+
+    /// Unwraps this `CachedPool`, returning the underlying block memory pool
+    pub fn into_inner(self) -> BlkPool { self.pool }
+
+    /// Reads block number `blockno` into `buf`, from the cache if possible
+    pub fn read(&self, buf: &mut [u8], blockno: i64) -> Result<(), io::Error> {
+        let bsize = self.check_buf(buf)?;
+        let epoch = {
+            let mut state = self.state.lock().unwrap();
+            let hit = match state.blocks.get(&blockno) {
+                Some((data, _)) => {
+                    buf[..bsize].copy_from_slice(data);
+                    true
+                }
+                None => false,
+            };
+            if hit {
+                state.stats.hits += 1;
+                state.touch(blockno);
+                return Ok(());
+            }
+            state.stats.misses += 1;
+            state.epoch
+        };
+
+        self.pool.read(buf, blockno)?;
+
+        let mut state = self.state.lock().unwrap();
+        // A write may have landed while we were reading, do not cache what could be stale
+        if state.epoch == epoch {
+            state.insert(blockno, buf[..bsize].to_vec(), self.capacity);
+        }
+        Ok(())
+    }
+
+    /// Writes a block from `buf` to block number `blockno` in the memory pool, updating the cache
+    ///
+    /// The write is **atomic**, see `BlkPool::write()`.
+    pub fn write(&self, buf: &[u8], blockno: i64) -> Result<(), io::Error> {
+        let bsize = self.check_buf(buf)?;
+        let epoch = {
+            let mut state = self.state.lock().unwrap();
+            state.epoch += 1;
+            state.epoch
+        };
+
+        // The lock is not held while writing, other blocks can be read and written meanwhile
+        let result = self.pool.write(buf, blockno);
+
+        let mut state = self.state.lock().unwrap();
+        // Another write or an invalidation raced with this one, the cached block could end up stale
+        if result.is_ok() && state.epoch == epoch {
+            state.insert(blockno, buf[..bsize].to_vec(), self.capacity);
+        } else {
+            state.remove(blockno);
+        }
+        // Reads that started before the write landed must not cache what they read
+        state.epoch += 1;
+        Ok(result?)
+    }
+
+    /// Checks `buf` holds a whole block and returns the block size
+    fn check_buf(&self, buf: &[u8]) -> Result<usize, io::Error> {
+        let bsize = self.block_size();
+        if buf.len() < bsize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Buffer of {} bytes is smaller than the block size {}",
+                                              buf.len(),
+                                              bsize)));
+        }
+        Ok(bsize)
+    }
+
+    /// Drops block number `blockno` from the cache
+    pub fn invalidate(&self, blockno: i64) {
+        let mut state = self.state.lock().unwrap();
+        state.epoch += 1;
+        state.remove(blockno);
+    }
+
+    /// Drops every block from the cache
+    pub fn invalidate_all(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.epoch += 1;
+        state.blocks.clear();
+        state.lru.clear();
+    }
+
+    /// The hit and miss counters
+    pub fn stats(&self) -> CacheStats { self.state.lock().unwrap().stats }
+
+    /// Resets the hit and miss counters
+    pub fn reset_stats(&self) { self.state.lock().unwrap().stats = CacheStats::default(); }
+}
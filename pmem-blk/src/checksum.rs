@@ -0,0 +1,227 @@
+//! Integrity checking for block memory pools
+//!
+//! **libpmemblk** guarantees that block writes are atomic, but it does not detect media corruption.
+//! A `ChecksumPool` keeps a CRC-32 for every block in a reserved area at the tail of the pool,
+//! verifies it on every read, and can `scrub()` the whole pool looking for silent corruption.
+
+use ::std::io;
+use ::std::sync::Mutex;
+
+pub use ::pmem::crc::crc32;
+
+use blkpool::BlkPool;
+
+/// Size in bytes of a stored checksum
+const CRC_SIZE: usize = 4;
+
+/// Size in bytes of the checksums stored for a block, the current one followed by the previous one
+const SLOT_SIZE: usize = 2 * CRC_SIZE;
+
+/// Result of a `scrub()` pass
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// Number of blocks inspected
+    pub checked: usize,
+    /// Blocks whose contents do not match their checksum
+    pub corrupt: Vec<i64>,
+    /// Blocks that could not be read, usually because they were already marked with `set_error()`
+    pub unreadable: Vec<i64>,
+}
+
+impl ScrubReport {
+    /// Whether the scrub found no corrupt nor unreadable blocks
+    pub fn is_clean(&self) -> bool { self.corrupt.is_empty() && self.unreadable.is_empty() }
+}
+
+/// Block memory pool with per-block checksums
+///
+/// The last blocks of the underlying pool are reserved to store the checksums,
+/// so `capacity()` is smaller than the capacity of the wrapped `BlkPool`.
+/// A pool must always be accessed through a `ChecksumPool` once it has been written through one,
+/// otherwise the stored checksums will go stale.
+///
+/// Blocks that have never been written read as zeroes and are considered valid.
+///
+/// The data block and its checksum are written with two separate atomic writes.
+/// The checksum goes first, stored along with the checksum of the data it replaces,
+/// and a block matching either of them is valid: a crash in between leaves the previous contents,
+/// still valid, rather than a stale checksum.
+pub struct ChecksumPool {
+    pool: BlkPool,
+    capacity: usize,
+    // Serializes the read-modify-write of the checksum blocks, and writes against `confirm_corrupt()`
+    lock: Mutex<()>,
+}
+
+impl ChecksumPool {
+    /// Wraps `pool`, reserving the space needed for the checksums at the tail of the pool
+    pub fn new(pool: BlkPool) -> Result<Self, io::Error> {
+        let per_block = pool.block_size() / SLOT_SIZE;
+        if per_block == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Block size {} is too small to hold a checksum",
+                                              pool.block_size())));
+        }
+
+        let nblocks = pool.capacity();
+        // Largest number of data blocks whose checksums fit in the remaining blocks
+        let mut capacity = nblocks * per_block / (per_block + 1);
+        while capacity > 0 && capacity + capacity.div_ceil(per_block) > nblocks {
+            capacity -= 1;
+        }
+        if capacity == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "The pool is too small to hold any checksummed block"));
+        }
+
+        Ok(ChecksumPool { pool, capacity, lock: Mutex::new(()) })
+    }
+
+    /// The block size for this pool
+    pub fn block_size(&self) -> usize { self.pool.block_size() }
+
+    /// The capacity of the pool in number of blocks, excluding the blocks reserved for checksums
+    pub fn capacity(&self) -> usize { self.capacity }
+
+    /// The underlying block memory pool
+    ///
+    /// Writing to it directly bypasses the checksums.
+    pub fn get_ref(&self) -> &BlkPool { &self.pool }
+
+    /// Unwraps this `ChecksumPool`, returning the underlying block memory pool
+    pub fn into_inner(self) -> BlkPool { self.pool }
+
+    /// Reads block number `blockno` from the memory pool into `buf`, verifying its checksum
+    ///
+    /// Fails with `ErrorKind::InvalidData` if the contents of the block do not match its checksum.
+    pub fn read(&self, buf: &mut [u8], blockno: i64) -> Result<(), io::Error> {
+        self.check_args(buf, blockno)?;
+        self.pool.read(buf, blockno)?;
+        if !self.verify(buf, blockno)? && self.confirm_corrupt(buf, blockno, false)? {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Checksum mismatch on block {}", blockno)));
+        }
+        Ok(())
+    }
+
+    /// Writes a block from `buf` to block number `blockno` in the memory pool and updates its checksum
+    pub fn write(&self, buf: &[u8], blockno: i64) -> Result<(), io::Error> {
+        self.check_args(buf, blockno)?;
+        let crc = crc32(&buf[..self.block_size()]);
+
+        let _guard = self.lock.lock().unwrap();
+        let (crc_blockno, pos) = self.crc_location(blockno);
+        let mut crc_block = vec![0; self.block_size()];
+        self.pool.read(&mut crc_block, crc_blockno)?;
+        let (current, _) = stored_crcs(&crc_block, pos);
+
+        // The contents being replaced stay valid until the new ones are written,
+        // unless they are corrupt or unreadable already
+        let mut old = vec![0; self.block_size()];
+        let previous = if self.pool.read(&mut old, blockno).is_ok() && self.verify(&old, blockno)? {
+            crc32(&old)
+        } else {
+            current
+        };
+        crc_block[pos..pos + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+        crc_block[pos + CRC_SIZE..pos + SLOT_SIZE].copy_from_slice(&previous.to_le_bytes());
+        self.pool.write(&crc_block, crc_blockno)?;
+
+        Ok(self.pool.write(buf, blockno)?)
+    }
+
+    /// Verifies every block in the pool
+    ///
+    /// If `mark` is `true` corrupt blocks are flagged with `BlkPool::set_error()`,
+    /// subsequent reads of those blocks will fail until they are written again.
+    pub fn scrub(&self, mark: bool) -> Result<ScrubReport, io::Error> {
+        let mut report = ScrubReport::default();
+        self.scrub_range(0, self.capacity, mark, &mut report)?;
+        Ok(report)
+    }
+
+    /// Verifies `count` blocks starting at block number `start`, accumulating the results into `report`
+    ///
+    /// This allows a scrub to be spread over time, for example from a background thread.
+    pub fn scrub_range(&self,
+                       start: usize,
+                       count: usize,
+                       mark: bool,
+                       report: &mut ScrubReport)
+                       -> Result<(), io::Error> {
+        let end = ::std::cmp::min(start.saturating_add(count), self.capacity);
+        let mut buf = vec![0; self.block_size()];
+        for blockno in start..end {
+            let blockno = blockno as i64;
+            report.checked += 1;
+            if self.pool.read(&mut buf, blockno).is_err() {
+                report.unreadable.push(blockno);
+                continue;
+            }
+            if !self.verify(&buf, blockno)? && self.confirm_corrupt(&mut buf, blockno, mark)? {
+                report.corrupt.push(blockno);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_args(&self, buf: &[u8], blockno: i64) -> Result<(), io::Error> {
+        if buf.len() < self.block_size() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Buffer of {} bytes is smaller than the block size {}",
+                                              buf.len(),
+                                              self.block_size())));
+        }
+        if blockno < 0 || blockno as usize >= self.capacity {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Block {} is out of range, the capacity is {}",
+                                              blockno,
+                                              self.capacity)));
+        }
+        Ok(())
+    }
+
+    /// Block number and byte position within that block of the checksums for `blockno`
+    fn crc_location(&self, blockno: i64) -> (i64, usize) {
+        let per_block = self.block_size() / SLOT_SIZE;
+        let blockno = blockno as usize;
+        ((self.capacity + blockno / per_block) as i64, (blockno % per_block) * SLOT_SIZE)
+    }
+
+    /// Reads block `blockno` and its checksum again with the lock held, after `verify()` failed
+    ///
+    /// A mismatch seen without the lock may be a checksum read after several writes went by.
+    /// If `mark` is `true` a corrupt block is flagged with `BlkPool::set_error()`, before any write can fix it.
+    fn confirm_corrupt(&self, buf: &mut [u8], blockno: i64, mark: bool) -> Result<bool, io::Error> {
+        let _guard = self.lock.lock().unwrap();
+        self.pool.read(buf, blockno)?;
+        if self.verify(buf, blockno)? {
+            return Ok(false);
+        }
+        if mark {
+            self.pool.set_error(blockno)?;
+        }
+        Ok(true)
+    }
+
+    fn verify(&self, buf: &[u8], blockno: i64) -> Result<bool, io::Error> {
+        let (crc_blockno, pos) = self.crc_location(blockno);
+        let mut crc_block = vec![0; self.block_size()];
+        self.pool.read(&mut crc_block, crc_blockno)?;
+        let (current, previous) = stored_crcs(&crc_block, pos);
+
+        let buf = &buf[..self.block_size()];
+        let crc = crc32(buf);
+        // A zero checksum on a zeroed block is a block that has never been written
+        Ok(crc == current || crc == previous || (current == 0 && buf.iter().all(|b| *b == 0)))
+    }
+}
+
+/// The current and previous checksums stored at `pos` in a checksum block
+fn stored_crcs(crc_block: &[u8], pos: usize) -> (u32, u32) {
+    let mut current = [0; CRC_SIZE];
+    current.copy_from_slice(&crc_block[pos..pos + CRC_SIZE]);
+    let mut previous = [0; CRC_SIZE];
+    previous.copy_from_slice(&crc_block[pos + CRC_SIZE..pos + SLOT_SIZE]);
+    (u32::from_le_bytes(current), u32::from_le_bytes(previous))
+}
@@ -5,16 +5,26 @@
 //! >
 //! > The official **libpmemblk** documentation can be found at: [http://pmem.io/nvml/libpmemblk/](http://pmem.io/nvml/libpmemblk/)
 
+extern crate pmem;
 extern crate pmemblk_sys;
 extern crate libc;
 
 // Modules
 
 pub mod blkpool;
+pub mod checksum;
+pub mod cache;
+pub mod nbd;
+pub mod volume;
+pub mod records;
 
 // Re-exports
 
-pub use blkpool::BlkPool;
+pub use blkpool::{BlkPool, BlkPoolOptions};
+pub use checksum::ChecksumPool;
+pub use cache::CachedPool;
+pub use volume::BlkVolume;
+pub use records::RecordStore;
 
 // module - lib
 
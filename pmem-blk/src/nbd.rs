@@ -0,0 +1,344 @@
+//! Network Block Device server for block memory pools
+//!
+//! Exposes a `BlkPool` as a single NBD export, so it can be attached with `nbd-client` and used by regular tools.
+//! Only the fixed newstyle handshake and simple replies are supported.
+//!
+//! NBD commands map onto the pool as follows:
+//!
+//! - `READ` and `WRITE` use `BlkPool::read()` and `BlkPool::write()`.
+//!   Requests that do not cover whole blocks read-modify-write the blocks at the edges,
+//!   only whole blocks are written atomically.
+//! - `TRIM` uses `BlkPool::set_zero()` on the blocks fully covered by the request.
+//! - `FLUSH` is a no-op, every write is durable by the time it is acknowledged.
+//!
+//! The protocol is described at [https://github.com/NetworkBlockDevice/nbd/blob/master/doc/proto.md]
+//! (https://github.com/NetworkBlockDevice/nbd/blob/master/doc/proto.md)
+
+use ::std::cmp;
+use ::std::error::Error;
+use ::std::io::{self, Read, Write};
+
+use blkpool::BlkPool;
+
+// Handshake
+
+const NBDMAGIC: u64 = 0x4e42_444d_4147_4943;
+const IHAVEOPT: u64 = 0x4948_4156_454f_5054;
+const REPLY_MAGIC: u64 = 0x0003_e889_0455_65a9;
+
+const FLAG_FIXED_NEWSTYLE: u16 = 1 << 0;
+const FLAG_NO_ZEROES: u16 = 1 << 1;
+
+const FLAG_C_FIXED_NEWSTYLE: u32 = 1 << 0;
+const FLAG_C_NO_ZEROES: u32 = 1 << 1;
+
+const OPT_EXPORT_NAME: u32 = 1;
+const OPT_ABORT: u32 = 2;
+const OPT_LIST: u32 = 3;
+const OPT_INFO: u32 = 6;
+const OPT_GO: u32 = 7;
+
+const REP_ACK: u32 = 1;
+const REP_SERVER: u32 = 2;
+const REP_INFO: u32 = 3;
+const REP_ERR_UNSUP: u32 = (1 << 31) + 1;
+const REP_ERR_INVALID: u32 = (1 << 31) + 3;
+
+const INFO_EXPORT: u16 = 0;
+const INFO_BLOCK_SIZE: u16 = 3;
+
+/// Largest option payload we are willing to read
+const MAX_OPTION_LEN: u32 = 4096;
+
+// Transmission
+
+const REQUEST_MAGIC: u32 = 0x2560_9513;
+const SIMPLE_REPLY_MAGIC: u32 = 0x6744_6698;
+
+const FLAG_HAS_FLAGS: u16 = 1 << 0;
+const FLAG_SEND_FLUSH: u16 = 1 << 2;
+const FLAG_SEND_TRIM: u16 = 1 << 5;
+
+const CMD_READ: u16 = 0;
+const CMD_WRITE: u16 = 1;
+const CMD_DISC: u16 = 2;
+const CMD_FLUSH: u16 = 3;
+const CMD_TRIM: u16 = 4;
+
+const EIO: u32 = 5;
+const EINVAL: u32 = 22;
+const ENOSPC: u32 = 28;
+
+/// Largest read or write request accepted
+///
+/// Bigger reads are refused with `EINVAL`, bigger writes close the connection.
+pub const MAX_REQUEST_LEN: u32 = 32 * 1024 * 1024;
+
+/// Name the export is listed under
+pub const EXPORT_NAME: &str = "pmem";
+
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    let mut b = [0; 2];
+    r.read_exact(&mut b)?;
+    Ok(u16::from_be_bytes(b))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_be_bytes(b))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_be_bytes(b))
+}
+
+fn invalid_data<E: Into<Box<dyn Error + Send + Sync>>>(msg: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Serves `pool` over `stream` until the client disconnects
+///
+/// Returns once the client sends `NBD_CMD_DISC`, aborts the handshake or closes the connection.
+pub fn serve<S: Read + Write>(pool: &BlkPool, mut stream: S) -> io::Result<()> {
+    if handshake(pool, &mut stream)? {
+        transmission(pool, &mut stream)
+    } else {
+        Ok(())
+    }
+}
+
+fn export_size(pool: &BlkPool) -> u64 { (pool.capacity() * pool.block_size()) as u64 }
+
+fn transmission_flags() -> u16 { FLAG_HAS_FLAGS | FLAG_SEND_FLUSH | FLAG_SEND_TRIM }
+
+fn option_reply<W: Write>(w: &mut W, option: u32, reply: u32, data: &[u8]) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(20 + data.len());
+    buf.extend_from_slice(&REPLY_MAGIC.to_be_bytes());
+    buf.extend_from_slice(&option.to_be_bytes());
+    buf.extend_from_slice(&reply.to_be_bytes());
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+    w.write_all(&buf)
+}
+
+/// Negotiates the export with the client
+///
+/// Returns `false` if the client aborted instead of entering the transmission phase.
+fn handshake<S: Read + Write>(pool: &BlkPool, stream: &mut S) -> io::Result<bool> {
+    let mut greeting = Vec::with_capacity(18);
+    greeting.extend_from_slice(&NBDMAGIC.to_be_bytes());
+    greeting.extend_from_slice(&IHAVEOPT.to_be_bytes());
+    greeting.extend_from_slice(&(FLAG_FIXED_NEWSTYLE | FLAG_NO_ZEROES).to_be_bytes());
+    stream.write_all(&greeting)?;
+    stream.flush()?;
+
+    let client_flags = read_u32(stream)?;
+    if client_flags & FLAG_C_FIXED_NEWSTYLE == 0 {
+        return Err(invalid_data("Client does not support the fixed newstyle handshake"));
+    }
+    let no_zeroes = client_flags & FLAG_C_NO_ZEROES != 0;
+
+    loop {
+        if read_u64(stream)? != IHAVEOPT {
+            return Err(invalid_data("Bad option magic"));
+        }
+        let option = read_u32(stream)?;
+        let len = read_u32(stream)?;
+        if len > MAX_OPTION_LEN {
+            return Err(invalid_data(format!("Option {} is too long ({} bytes)", option, len)));
+        }
+        let mut data = vec![0; len as usize];
+        stream.read_exact(&mut data)?;
+
+        match option {
+            OPT_EXPORT_NAME => {
+                let mut reply = Vec::with_capacity(10 + 124);
+                reply.extend_from_slice(&export_size(pool).to_be_bytes());
+                reply.extend_from_slice(&transmission_flags().to_be_bytes());
+                if !no_zeroes {
+                    reply.extend_from_slice(&[0; 124]);
+                }
+                stream.write_all(&reply)?;
+                stream.flush()?;
+                return Ok(true);
+            }
+            OPT_ABORT => {
+                option_reply(stream, option, REP_ACK, &[])?;
+                stream.flush()?;
+                return Ok(false);
+            }
+            OPT_LIST => {
+                if !data.is_empty() {
+                    option_reply(stream, option, REP_ERR_INVALID, &[])?;
+                } else {
+                    let name = EXPORT_NAME.as_bytes();
+                    let mut server = Vec::with_capacity(4 + name.len());
+                    server.extend_from_slice(&(name.len() as u32).to_be_bytes());
+                    server.extend_from_slice(name);
+                    option_reply(stream, option, REP_SERVER, &server)?;
+                    option_reply(stream, option, REP_ACK, &[])?;
+                }
+            }
+            OPT_INFO | OPT_GO => {
+                // u32 name length, name, u16 number of requests, u16 requests
+                let valid = data.len() >= 6 && {
+                    let name_len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+                    data.len() >= 4 + name_len + 2 && {
+                        let n = u16::from_be_bytes([data[4 + name_len], data[5 + name_len]]) as usize;
+                        data.len() == 4 + name_len + 2 + 2 * n
+                    }
+                };
+                if !valid {
+                    option_reply(stream, option, REP_ERR_INVALID, &[])?;
+                } else {
+                    let mut export = Vec::with_capacity(12);
+                    export.extend_from_slice(&INFO_EXPORT.to_be_bytes());
+                    export.extend_from_slice(&export_size(pool).to_be_bytes());
+                    export.extend_from_slice(&transmission_flags().to_be_bytes());
+                    option_reply(stream, option, REP_INFO, &export)?;
+
+                    let bsize = pool.block_size() as u32;
+                    let mut block_size = Vec::with_capacity(14);
+                    block_size.extend_from_slice(&INFO_BLOCK_SIZE.to_be_bytes());
+                    block_size.extend_from_slice(&1u32.to_be_bytes());
+                    block_size.extend_from_slice(&bsize.to_be_bytes());
+                    block_size.extend_from_slice(&MAX_REQUEST_LEN.to_be_bytes());
+                    option_reply(stream, option, REP_INFO, &block_size)?;
+
+                    option_reply(stream, option, REP_ACK, &[])?;
+                    if option == OPT_GO {
+                        stream.flush()?;
+                        return Ok(true);
+                    }
+                }
+            }
+            _ => option_reply(stream, option, REP_ERR_UNSUP, &[])?,
+        }
+        stream.flush()?;
+    }
+}
+
+fn simple_reply<W: Write>(w: &mut W, error: u32, handle: u64, data: &[u8]) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(16 + data.len());
+    buf.extend_from_slice(&SIMPLE_REPLY_MAGIC.to_be_bytes());
+    buf.extend_from_slice(&error.to_be_bytes());
+    buf.extend_from_slice(&handle.to_be_bytes());
+    buf.extend_from_slice(data);
+    w.write_all(&buf)?;
+    w.flush()
+}
+
+fn transmission<S: Read + Write>(pool: &BlkPool, stream: &mut S) -> io::Result<()> {
+    let size = export_size(pool);
+    loop {
+        let magic = match read_u32(stream) {
+            Ok(magic) => magic,
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        if magic != REQUEST_MAGIC {
+            return Err(invalid_data("Bad request magic"));
+        }
+        let _flags = read_u16(stream)?;
+        let command = read_u16(stream)?;
+        let handle = read_u64(stream)?;
+        let offset = read_u64(stream)?;
+        let len = read_u32(stream)?;
+
+        let in_range = offset.checked_add(len as u64).is_some_and(|end| end <= size);
+
+        match command {
+            CMD_READ => {
+                if !in_range || len > MAX_REQUEST_LEN {
+                    simple_reply(stream, EINVAL, handle, &[])?;
+                    continue;
+                }
+                let mut data = vec![0; len as usize];
+                match read_range(pool, offset, &mut data) {
+                    Ok(()) => simple_reply(stream, 0, handle, &data)?,
+                    Err(_) => simple_reply(stream, EIO, handle, &[])?,
+                }
+            }
+            CMD_WRITE => {
+                if len > MAX_REQUEST_LEN {
+                    // We cannot skip the payload without reading it, give up on the connection
+                    return Err(invalid_data(format!("Write request of {} bytes is too large", len)));
+                }
+                let mut data = vec![0; len as usize];
+                stream.read_exact(&mut data)?;
+                if !in_range {
+                    simple_reply(stream, ENOSPC, handle, &[])?;
+                    continue;
+                }
+                match write_range(pool, offset, &data) {
+                    Ok(()) => simple_reply(stream, 0, handle, &[])?,
+                    Err(_) => simple_reply(stream, EIO, handle, &[])?,
+                }
+            }
+            CMD_TRIM => {
+                if !in_range {
+                    simple_reply(stream, EINVAL, handle, &[])?;
+                    continue;
+                }
+                match trim_range(pool, offset, len as u64) {
+                    Ok(()) => simple_reply(stream, 0, handle, &[])?,
+                    Err(_) => simple_reply(stream, EIO, handle, &[])?,
+                }
+            }
+            CMD_FLUSH => simple_reply(stream, 0, handle, &[])?,
+            CMD_DISC => return Ok(()),
+            _ => simple_reply(stream, EINVAL, handle, &[])?,
+        }
+    }
+}
+
+fn read_range(pool: &BlkPool, offset: u64, data: &mut [u8]) -> io::Result<()> {
+    let bsize = pool.block_size() as u64;
+    let mut block = vec![0; bsize as usize];
+    let mut pos = 0;
+    while pos < data.len() {
+        let abs = offset + pos as u64;
+        let blockno = abs / bsize;
+        let start = (abs % bsize) as usize;
+        let n = cmp::min(bsize as usize - start, data.len() - pos);
+        pool.read(&mut block, blockno as i64)?;
+        data[pos..pos + n].copy_from_slice(&block[start..start + n]);
+        pos += n;
+    }
+    Ok(())
+}
+
+fn write_range(pool: &BlkPool, offset: u64, data: &[u8]) -> io::Result<()> {
+    let bsize = pool.block_size() as u64;
+    let mut block = vec![0; bsize as usize];
+    let mut pos = 0;
+    while pos < data.len() {
+        let abs = offset + pos as u64;
+        let blockno = abs / bsize;
+        let start = (abs % bsize) as usize;
+        let n = cmp::min(bsize as usize - start, data.len() - pos);
+        if n == bsize as usize {
+            pool.write(&data[pos..pos + n], blockno as i64)?;
+        } else {
+            pool.read(&mut block, blockno as i64)?;
+            block[start..start + n].copy_from_slice(&data[pos..pos + n]);
+            pool.write(&block, blockno as i64)?;
+        }
+        pos += n;
+    }
+    Ok(())
+}
+
+fn trim_range(pool: &BlkPool, offset: u64, len: u64) -> io::Result<()> {
+    let bsize = pool.block_size() as u64;
+    // Only the blocks fully covered by the request, trimming is advisory
+    let first = offset.div_ceil(bsize);
+    let end = (offset + len) / bsize;
+    for blockno in first..end {
+        pool.set_zero(blockno as i64)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,368 @@
+//! Variable-length records stored in slotted pages
+//!
+//! A `RecordStore` lays out a slotted page inside every block of a `BlkPool`, giving a tiny heap file.
+//! Each page starts with a header followed by an array of slots, the record data grows from the end of the page:
+//!
+//! ```text
+//! +--------+--------+--------+-----+-------------+--------+--------+
+//! | header | slot 0 | slot 1 | ... | free space  | data 1 | data 0 |
+//! +--------+--------+--------+-----+-------------+--------+--------+
+//! ```
+//!
+//! Every operation rewrites a single page with one `BlkPool::write()`,
+//! so inserts, updates and deletes are crash consistent.
+//! Records are addressed by a `RecordId` that stays valid until the record is deleted,
+//! after which its slot may be reused.
+
+use ::std::io;
+use ::std::sync::Mutex;
+
+use blkpool::BlkPool;
+
+/// Marks a formatted page, `"PREC"`
+const PAGE_MAGIC: u32 = 0x4345_5250;
+/// magic, slot count, start of the data area, reserved
+const HEADER_SIZE: usize = 16;
+/// offset, length
+const SLOT_SIZE: usize = 8;
+/// Slots are addressed with 16 bits in `RecordId::to_u64()`
+const MAX_SLOTS: usize = 1 << 16;
+
+/// Identifier of a record in a `RecordStore`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RecordId {
+    /// Block number of the page holding the record
+    pub page: u64,
+    /// Slot within the page
+    pub slot: u16,
+}
+
+impl RecordId {
+    /// Packs the identifier in a single integer
+    pub fn to_u64(&self) -> u64 { self.page << 16 | self.slot as u64 }
+
+    /// Unpacks an identifier created by `to_u64()`
+    pub fn from_u64(id: u64) -> Self { RecordId { page: id >> 16, slot: id as u16 } }
+}
+
+fn get_u32(buf: &[u8], pos: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&buf[pos..pos + 4]);
+    u32::from_le_bytes(b)
+}
+
+fn put_u32(buf: &mut [u8], pos: usize, val: u32) { buf[pos..pos + 4].copy_from_slice(&val.to_le_bytes()); }
+
+/// In-memory copy of a slotted page
+struct Page {
+    buf: Vec<u8>,
+}
+
+impl Page {
+    fn read(pool: &BlkPool, blockno: u64) -> Result<Page, io::Error> {
+        let mut buf = vec![0; pool.block_size()];
+        pool.read(&mut buf, blockno as i64)?;
+        let page = Page { buf };
+        match get_u32(&page.buf, 0) {
+            // never written
+            0 if page.buf.iter().all(|b| *b == 0) => Ok(Page::empty(page.buf.len())),
+            PAGE_MAGIC if page.is_valid() => Ok(page),
+            _ => {
+                Err(io::Error::new(io::ErrorKind::InvalidData,
+                                   format!("Block {} is not a valid record page", blockno)))
+            }
+        }
+    }
+
+    fn empty(bsize: usize) -> Page {
+        let mut page = Page { buf: vec![0; bsize] };
+        put_u32(&mut page.buf, 0, PAGE_MAGIC);
+        page.set_data_start(bsize);
+        page
+    }
+
+    fn is_valid(&self) -> bool {
+        let bsize = self.buf.len();
+        let slots_end = HEADER_SIZE + self.slot_count() * SLOT_SIZE;
+        slots_end <= self.data_start() && self.data_start() <= bsize &&
+        (0..self.slot_count()).all(|i| {
+            let (offset, len) = self.slot(i);
+            offset == 0 || (offset >= self.data_start() && offset + len <= bsize)
+        })
+    }
+
+    fn slot_count(&self) -> usize { get_u32(&self.buf, 4) as usize }
+
+    fn set_slot_count(&mut self, count: usize) { put_u32(&mut self.buf, 4, count as u32) }
+
+    fn data_start(&self) -> usize { get_u32(&self.buf, 8) as usize }
+
+    fn set_data_start(&mut self, start: usize) { put_u32(&mut self.buf, 8, start as u32) }
+
+    /// Offset and length of a slot, an offset of 0 marks a free slot
+    fn slot(&self, i: usize) -> (usize, usize) {
+        let pos = HEADER_SIZE + i * SLOT_SIZE;
+        (get_u32(&self.buf, pos) as usize, get_u32(&self.buf, pos + 4) as usize)
+    }
+
+    fn set_slot(&mut self, i: usize, offset: usize, len: usize) {
+        let pos = HEADER_SIZE + i * SLOT_SIZE;
+        put_u32(&mut self.buf, pos, offset as u32);
+        put_u32(&mut self.buf, pos + 4, len as u32);
+    }
+
+    fn record(&self, i: usize) -> Option<&[u8]> {
+        if i >= self.slot_count() {
+            return None;
+        }
+        match self.slot(i) {
+            (0, _) => None,
+            (offset, len) => Some(&self.buf[offset..offset + len]),
+        }
+    }
+
+    fn free_slot(&self) -> Option<usize> { (0..self.slot_count()).find(|&i| self.slot(i).0 == 0) }
+
+    /// Bytes available for new data, counting the space compaction would reclaim
+    fn free_space(&self) -> usize {
+        let used: usize = (0..self.slot_count()).map(|i| self.slot(i)).filter(|s| s.0 != 0).map(|s| s.1).sum();
+        self.buf.len() - HEADER_SIZE - self.slot_count() * SLOT_SIZE - used
+    }
+
+    /// Bytes a new record of `len` bytes takes from `free_space()`
+    fn needed(&self, len: usize) -> Option<usize> {
+        if self.free_slot().is_some() {
+            Some(len)
+        } else if self.slot_count() < MAX_SLOTS {
+            Some(len + SLOT_SIZE)
+        } else {
+            None
+        }
+    }
+
+    /// Moves all the records to the end of the page, leaving a contiguous free area
+    fn compact(&mut self) {
+        let bsize = self.buf.len();
+        let mut new = vec![0; bsize];
+        new[..HEADER_SIZE + self.slot_count() * SLOT_SIZE]
+            .copy_from_slice(&self.buf[..HEADER_SIZE + self.slot_count() * SLOT_SIZE]);
+        let mut end = bsize;
+        for i in 0..self.slot_count() {
+            let (offset, len) = self.slot(i);
+            if offset != 0 {
+                end -= len;
+                new[end..end + len].copy_from_slice(&self.buf[offset..offset + len]);
+                let pos = HEADER_SIZE + i * SLOT_SIZE;
+                put_u32(&mut new, pos, end as u32);
+            }
+        }
+        self.buf = new;
+        self.set_data_start(end);
+    }
+
+    /// Stores `data` in slot `i`, which must be free, the caller checked there is enough space
+    fn place(&mut self, i: usize, data: &[u8]) {
+        let slots_end = HEADER_SIZE + self.slot_count() * SLOT_SIZE;
+        if self.data_start() - slots_end < data.len() {
+            self.compact();
+        }
+        let start = self.data_start() - data.len();
+        self.buf[start..start + data.len()].copy_from_slice(data);
+        self.set_data_start(start);
+        // a zero length record still needs a non-zero offset to be told apart from a free slot
+        self.set_slot(i, if data.is_empty() { self.buf.len() } else { start }, data.len());
+    }
+
+    fn insert(&mut self, data: &[u8]) -> usize {
+        let i = match self.free_slot() {
+            Some(i) => i,
+            None => {
+                // make room for the new slot before it overwrites any data
+                let slots_end = HEADER_SIZE + self.slot_count() * SLOT_SIZE;
+                if self.data_start() - slots_end < data.len() + SLOT_SIZE {
+                    self.compact();
+                }
+                let i = self.slot_count();
+                self.set_slot_count(i + 1);
+                self.set_slot(i, 0, 0);
+                i
+            }
+        };
+        self.place(i, data);
+        i
+    }
+
+    fn remove(&mut self, i: usize) {
+        self.set_slot(i, 0, 0);
+        // trim trailing free slots
+        let mut count = self.slot_count();
+        while count > 0 && self.slot(count - 1).0 == 0 {
+            count -= 1;
+        }
+        self.set_slot_count(count);
+    }
+}
+
+/// Heap file of variable-length records on top of a `BlkPool`
+///
+/// Every block of the pool is a page. A record must fit in a single page,
+/// see `max_record_size()`.
+pub struct RecordStore {
+    pool: BlkPool,
+    /// Free bytes on each page, guarded so page updates are serialized
+    free: Mutex<Vec<usize>>,
+}
+
+impl RecordStore {
+    /// Opens a record store on `pool`, formatting pages lazily as they are used
+    ///
+    /// Every page is read to build the free space map.
+    /// Fails with `ErrorKind::InvalidData` if a block holds something other than a record page.
+    pub fn new(pool: BlkPool) -> Result<Self, io::Error> {
+        let mut free = Vec::with_capacity(pool.capacity());
+        for blockno in 0..pool.capacity() as u64 {
+            free.push(Page::read(&pool, blockno)?.free_space());
+        }
+        Ok(RecordStore { pool, free: Mutex::new(free) })
+    }
+
+    /// The underlying block memory pool
+    pub fn get_ref(&self) -> &BlkPool { &self.pool }
+
+    /// Unwraps this `RecordStore`, returning the underlying block memory pool
+    pub fn into_inner(self) -> BlkPool { self.pool }
+
+    /// The largest record that fits in a page
+    pub fn max_record_size(&self) -> usize { self.pool.block_size() - HEADER_SIZE - SLOT_SIZE }
+
+    fn page(&self, id: RecordId) -> Result<Page, io::Error> {
+        if id.page >= self.pool.capacity() as u64 {
+            return Err(not_found(id));
+        }
+        Page::read(&self.pool, id.page)
+    }
+
+    fn check_size(&self, data: &[u8]) -> Result<(), io::Error> {
+        if data.len() > self.max_record_size() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Record of {} bytes is larger than the maximum of {}",
+                                              data.len(),
+                                              self.max_record_size())));
+        }
+        Ok(())
+    }
+
+    /// Inserts a new record, returning its identifier
+    pub fn insert(&self, data: &[u8]) -> Result<RecordId, io::Error> {
+        self.check_size(data)?;
+        let mut free = self.free.lock().unwrap();
+        // The free space map may overestimate when the page is out of slots, keep looking in that case
+        for blockno in 0..free.len() {
+            if free[blockno] < data.len() {
+                continue;
+            }
+            let mut page = Page::read(&self.pool, blockno as u64)?;
+            match page.needed(data.len()) {
+                Some(needed) if needed <= page.free_space() => {
+                    let slot = page.insert(data);
+                    self.pool.write(&page.buf, blockno as i64)?;
+                    free[blockno] = page.free_space();
+                    return Ok(RecordId { page: blockno as u64, slot: slot as u16 });
+                }
+                _ => {}
+            }
+        }
+        Err(io::Error::other("The record store is full"))
+    }
+
+    /// Reads the record `id`
+    ///
+    /// Fails with `ErrorKind::NotFound` if there is no such record.
+    pub fn get(&self, id: RecordId) -> Result<Vec<u8>, io::Error> {
+        let page = self.page(id)?;
+        page.record(id.slot as usize).map(|r| r.to_vec()).ok_or_else(|| not_found(id))
+    }
+
+    /// Replaces the contents of record `id` with `data`
+    ///
+    /// The record keeps its identifier, so the new contents must fit in the page holding it.
+    /// Fails with `ErrorKind::NotFound` if there is no such record.
+    pub fn update(&self, id: RecordId, data: &[u8]) -> Result<(), io::Error> {
+        self.check_size(data)?;
+        let mut free = self.free.lock().unwrap();
+        let mut page = self.page(id)?;
+        let slot = id.slot as usize;
+        let old_len = match page.record(slot) {
+            Some(r) => r.len(),
+            None => return Err(not_found(id)),
+        };
+        if page.free_space() + old_len < data.len() {
+            return Err(io::Error::other(format!("Record {:?} does not fit in its page after the update", id)));
+        }
+        page.set_slot(slot, 0, 0);
+        page.place(slot, data);
+        self.pool.write(&page.buf, id.page as i64)?;
+        free[id.page as usize] = page.free_space();
+        Ok(())
+    }
+
+    /// Deletes record `id`
+    ///
+    /// Fails with `ErrorKind::NotFound` if there is no such record.
+    pub fn delete(&self, id: RecordId) -> Result<(), io::Error> {
+        let mut free = self.free.lock().unwrap();
+        let mut page = self.page(id)?;
+        if page.record(id.slot as usize).is_none() {
+            return Err(not_found(id));
+        }
+        page.remove(id.slot as usize);
+        self.pool.write(&page.buf, id.page as i64)?;
+        free[id.page as usize] = page.free_space();
+        Ok(())
+    }
+
+    /// Iterates over all the records, in identifier order
+    pub fn iter(&self) -> Records<'_> {
+        Records { store: self, page: 0, records: Vec::new().into_iter() }
+    }
+}
+
+fn not_found(id: RecordId) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("Record {:?} does not exist", id))
+}
+
+/// Iterator over the records of a `RecordStore`, created by `RecordStore::iter()`
+///
+/// Each page is read as a whole, concurrent updates are seen at page granularity.
+pub struct Records<'a> {
+    store: &'a RecordStore,
+    page: u64,
+    records: ::std::vec::IntoIter<(RecordId, Vec<u8>)>,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<(RecordId, Vec<u8>), io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.records.next() {
+                return Some(Ok(record));
+            }
+            if self.page >= self.store.pool.capacity() as u64 {
+                return None;
+            }
+            let blockno = self.page;
+            self.page += 1;
+            let page = match Page::read(&self.store.pool, blockno) {
+                Ok(page) => page,
+                Err(err) => return Some(Err(err)),
+            };
+            let records: Vec<_> = (0..page.slot_count())
+                .filter_map(|i| {
+                    page.record(i).map(|r| (RecordId { page: blockno, slot: i as u16 }, r.to_vec()))
+                })
+                .collect();
+            self.records = records.into_iter();
+        }
+    }
+}
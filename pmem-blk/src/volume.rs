@@ -0,0 +1,326 @@
+//! Volumes spanning several block memory pools
+//!
+//! A `BlkVolume` composes multiple `BlkPool`s, all with the same block size, behind the `BlkPool` read/write API:
+//!
+//! - `Layout::Mirror` (RAID-1) writes every block to all the members and reads it from any of them.
+//! - `Layout::Stripe` (RAID-0) spreads consecutive blocks round-robin across the members.
+
+use ::std::io;
+use ::std::sync::atomic::{AtomicUsize, Ordering};
+use ::std::sync::{Mutex, MutexGuard};
+
+use blkpool::BlkPool;
+
+/// Number of locks serializing the mirrored writes of a block with its repairs
+const LOCK_STRIPES: usize = 64;
+
+/// How blocks are laid out across the members of a `BlkVolume`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Every member holds a full copy of the volume
+    Mirror,
+    /// Block `n` lives in member `n % members` at block `n / members`
+    Stripe,
+}
+
+/// Result of a `repair()` or `resync()` pass
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Number of blocks inspected
+    pub checked: usize,
+    /// Blocks where at least one member was rewritten
+    pub repaired: Vec<i64>,
+    /// Blocks that could not be read from any member, left as they are
+    pub unreadable: Vec<i64>,
+    /// Blocks where rewriting a member failed, their copies may still diverge
+    pub failed: Vec<i64>,
+}
+
+/// Block volume over multiple block memory pools
+pub struct BlkVolume {
+    layout: Layout,
+    members: Vec<BlkPool>,
+    block_size: usize,
+    /// Number of blocks used on each member
+    member_capacity: usize,
+    /// Member the next mirrored read starts at
+    next: AtomicUsize,
+    /// Block `n` of a mirrored volume is written and repaired holding lock `n % LOCK_STRIPES`,
+    /// so a repair never overwrites a newer copy with the one it read
+    locks: Vec<Mutex<()>>,
+}
+
+fn no_redundancy() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "The volume has no redundant copies")
+}
+
+impl BlkVolume {
+    /// Creates a mirrored (RAID-1) volume
+    ///
+    /// The capacity of the volume is the capacity of its smallest member.
+    pub fn mirror(members: Vec<BlkPool>) -> Result<Self, io::Error> { BlkVolume::new(Layout::Mirror, members) }
+
+    /// Creates a striped (RAID-0) volume
+    ///
+    /// The capacity of the volume is the capacity of its smallest member times the number of members.
+    pub fn stripe(members: Vec<BlkPool>) -> Result<Self, io::Error> { BlkVolume::new(Layout::Stripe, members) }
+
+    /// Creates a volume with the given `layout`
+    ///
+    /// All the members must have the same block size.
+    pub fn new(layout: Layout, members: Vec<BlkPool>) -> Result<Self, io::Error> {
+        let block_size = match members.first() {
+            Some(pool) => pool.block_size(),
+            None => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "A volume needs at least one member"))
+            }
+        };
+        if let Some(pool) = members.iter().find(|p| p.block_size() != block_size) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Members have different block sizes ({} and {})",
+                                              block_size,
+                                              pool.block_size())));
+        }
+        let member_capacity = members.iter().map(|p| p.capacity()).min().unwrap();
+
+        Ok(BlkVolume {
+            layout,
+            members,
+            block_size,
+            member_capacity,
+            next: AtomicUsize::new(0),
+            locks: (0..LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+        })
+    }
+
+    /// The layout of this volume
+    pub fn layout(&self) -> Layout { self.layout }
+
+    /// The block size for this volume
+    pub fn block_size(&self) -> usize { self.block_size }
+
+    /// The capacity of the volume in number of blocks
+    pub fn capacity(&self) -> usize {
+        match self.layout {
+            Layout::Mirror => self.member_capacity,
+            Layout::Stripe => self.member_capacity * self.members.len(),
+        }
+    }
+
+    /// The member pools of this volume
+    pub fn members(&self) -> &[BlkPool] { &self.members }
+
+    /// Unwraps this `BlkVolume`, returning its members
+    pub fn into_members(self) -> Vec<BlkPool> { self.members }
+
+    fn check_index(&self, index: usize) -> Result<(), io::Error> {
+        if index >= self.members.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Member {} does not exist, the volume has {} members",
+                                              index,
+                                              self.members.len())));
+        }
+        Ok(())
+    }
+
+    fn lock(&self, blockno: i64) -> MutexGuard<'_, ()> {
+        self.locks[blockno as usize % LOCK_STRIPES].lock().unwrap()
+    }
+
+    fn check_blockno(&self, blockno: i64) -> Result<(), io::Error> {
+        if blockno < 0 || blockno as usize >= self.capacity() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Block {} is out of range, the capacity is {}",
+                                              blockno,
+                                              self.capacity())));
+        }
+        Ok(())
+    }
+
+    /// Reads block number `blockno` from the volume into `buf`
+    ///
+    /// Mirrored reads are spread across the members.
+    /// If a member fails to read the block, the next one is tried and the failing member is rewritten with the good copy.
+    pub fn read(&self, buf: &mut [u8], blockno: i64) -> Result<(), io::Error> {
+        self.check_blockno(blockno)?;
+        match self.layout {
+            Layout::Stripe => {
+                let n = self.members.len() as i64;
+                Ok(self.members[(blockno % n) as usize].read(buf, blockno / n)?)
+            }
+            Layout::Mirror => {
+                let n = self.members.len();
+                let start = self.next.fetch_add(1, Ordering::Relaxed) % n;
+                let mut failed: Vec<usize> = Vec::new();
+                let mut last_err = None;
+                for i in 0..n {
+                    let member = (start + i) % n;
+                    match self.members[member].read(buf, blockno) {
+                        Ok(()) => {
+                            if !failed.is_empty() {
+                                // A write may have landed since, read the good copy again holding writes off.
+                                // Best effort, the read itself succeeded
+                                let _guard = self.lock(blockno);
+                                if self.members[member].read(buf, blockno).is_ok() {
+                                    for &bad in &failed {
+                                        let _ = self.members[bad].write(buf, blockno);
+                                    }
+                                }
+                            }
+                            return Ok(());
+                        }
+                        Err(err) => {
+                            failed.push(member);
+                            last_err = Some(err);
+                        }
+                    }
+                }
+                Err(last_err.unwrap().into())
+            }
+        }
+    }
+
+    /// Writes a block from `buf` to block number `blockno` in the volume
+    ///
+    /// The write is **atomic** on each member, see `BlkPool::write()`.
+    /// A mirrored write is attempted on every member, if any of them fails the first error is returned
+    /// and the copies may have diverged until the block is written again or `repair()` is run.
+    pub fn write(&self, buf: &[u8], blockno: i64) -> Result<(), io::Error> {
+        self.check_blockno(blockno)?;
+        match self.layout {
+            Layout::Stripe => {
+                let n = self.members.len() as i64;
+                Ok(self.members[(blockno % n) as usize].write(buf, blockno / n)?)
+            }
+            Layout::Mirror => {
+                let _guard = self.lock(blockno);
+                let mut result = Ok(());
+                for member in &self.members {
+                    if let Err(err) = member.write(buf, blockno) {
+                        if result.is_ok() {
+                            result = Err(err);
+                        }
+                    }
+                }
+                result.map_err(io::Error::from)
+            }
+        }
+    }
+
+    /// Compares every block across the mirrors and rewrites the copies that diverge
+    ///
+    /// The copy held by most members wins, ties are broken in favor of the lowest numbered member.
+    /// Unreadable copies are always rewritten, blocks unreadable on every member are reported and skipped,
+    /// as are the blocks that fail to be rewritten.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` on a striped volume.
+    pub fn repair(&self) -> Result<RepairReport, io::Error> {
+        if self.layout != Layout::Mirror {
+            return Err(no_redundancy());
+        }
+
+        let mut report = RepairReport::default();
+        let mut copies: Vec<Option<Vec<u8>>> = vec![None; self.members.len()];
+        for blockno in 0..self.member_capacity as i64 {
+            report.checked += 1;
+            let _guard = self.lock(blockno);
+            for (copy, member) in copies.iter_mut().zip(&self.members) {
+                let mut buf = vec![0; self.block_size];
+                *copy = member.read(&mut buf, blockno).ok().map(|_| buf);
+            }
+
+            let mut best: Option<(usize, usize)> = None;
+            for (i, copy) in copies.iter().enumerate() {
+                if let Some(ref data) = *copy {
+                    let votes = copies.iter().filter(|c| c.as_ref() == Some(data)).count();
+                    if best.is_none_or(|(_, v)| votes > v) {
+                        best = Some((i, votes));
+                    }
+                }
+            }
+            let good = match best {
+                Some((i, _)) => copies[i].clone().unwrap(),
+                None => {
+                    report.unreadable.push(blockno);
+                    continue;
+                }
+            };
+
+            let (mut repaired, mut failed) = (false, false);
+            for (copy, member) in copies.iter().zip(&self.members) {
+                if copy.as_ref() != Some(&good) {
+                    match member.write(&good, blockno) {
+                        Ok(()) => repaired = true,
+                        Err(_) => failed = true,
+                    }
+                }
+            }
+            if repaired {
+                report.repaired.push(blockno);
+            }
+            if failed {
+                report.failed.push(blockno);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Replaces member number `index` with `pool`, returning the old member
+    ///
+    /// The new member must have the same block size and at least the capacity used on the other members.
+    /// On a mirrored volume call `resync()` afterwards to copy the data onto it.
+    pub fn replace(&mut self, index: usize, pool: BlkPool) -> Result<BlkPool, io::Error> {
+        self.check_index(index)?;
+        if pool.block_size() != self.block_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Block size {} does not match the volume block size {}",
+                                              pool.block_size(),
+                                              self.block_size)));
+        }
+        if pool.capacity() < self.member_capacity {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Capacity {} is smaller than the {} blocks used on each member",
+                                              pool.capacity(),
+                                              self.member_capacity)));
+        }
+        Ok(::std::mem::replace(&mut self.members[index], pool))
+    }
+
+    /// Copies every block from the other mirrors onto member number `index`
+    ///
+    /// Use it after `replace()` to bring a new member up to date.
+    /// Only the blocks that differ are written, those are reported as repaired.
+    /// Blocks unreadable on every other member or that fail to be written are reported and skipped.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` on a striped volume or a volume with a single member.
+    pub fn resync(&self, index: usize) -> Result<RepairReport, io::Error> {
+        if self.layout != Layout::Mirror || self.members.len() < 2 {
+            return Err(no_redundancy());
+        }
+        self.check_index(index)?;
+        let target = &self.members[index];
+        let mut report = RepairReport::default();
+        let mut buf = vec![0; self.block_size];
+        let mut current = vec![0; self.block_size];
+        for blockno in 0..self.member_capacity as i64 {
+            report.checked += 1;
+            let _guard = self.lock(blockno);
+            let found = self.members
+                            .iter()
+                            .enumerate()
+                            .any(|(i, member)| i != index && member.read(&mut buf, blockno).is_ok());
+            if !found {
+                report.unreadable.push(blockno);
+                continue;
+            }
+            if target.read(&mut current, blockno).is_ok() && current == buf {
+                continue;
+            }
+            match target.write(&buf, blockno) {
+                Ok(()) => report.repaired.push(blockno),
+                Err(_) => report.failed.push(blockno),
+            }
+        }
+        Ok(report)
+    }
+}
@@ -1,6 +1,10 @@
 extern crate pmem_blk;
 
+use ::std::ffi::OsStr;
 use ::std::fs;
+use ::std::io;
+use ::std::os::unix::ffi::OsStrExt;
+use ::std::os::unix::fs::PermissionsExt;
 use ::std::path::Path;
 
 use ::pmem_blk::BlkPool;
@@ -141,3 +145,103 @@ fn read() {
 
 #[test]
 fn version() { pmem_blk::check_version(1, 0).unwrap(); }
+
+#[test]
+fn set_zero() {
+    let path = Path::new("/tmp/test-set_zero.pmemblk");
+    if path.exists() {
+        fs::remove_file(&path).unwrap();
+    }
+
+    let p: BlkPool = BlkPool::create(path, 4 * 1024, 20 * 1024 * 1024).unwrap();
+    let buf = [1; 4 * 1024];
+    p.write(&buf, 1).unwrap();
+    p.set_zero(1).unwrap();
+
+    let mut buf = [1; 4 * 1024];
+    p.read(&mut buf, 1).unwrap();
+    assert_eq!(buf[0], 0);
+}
+
+#[test]
+fn open_error() {
+    let path = Path::new("/tmp/test-open_error.pmemblk");
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+    drop(BlkPool::create(path, 4 * 1024, 20 * 1024 * 1024).unwrap());
+
+    let err = match BlkPool::open(path, 512) {
+        Err(err) => err,
+        Ok(_) => panic!("Opened with the wrong block size"),
+    };
+    assert_eq!(err.operation(), "pmemblk_open");
+    assert_eq!(err.path(), Some(path));
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    // the reason comes from libpmemblk
+    assert!(err.message().is_some());
+}
+
+#[test]
+fn non_utf8_path() {
+    let path = Path::new(OsStr::from_bytes(b"/tmp/test-non_utf8_path-\xff\xfe.pmemblk"));
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+    drop(BlkPool::create(path, 4 * 1024, 20 * 1024 * 1024).unwrap());
+    assert!(BlkPool::check(path, 4 * 1024).unwrap());
+    assert_eq!(BlkPool::open_no_size(path).unwrap().block_size(), 4 * 1024);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn nul_in_path() {
+    let path = "/tmp/test-nul\0in_path.pmemblk";
+    let err = BlkPool::create(path, 4 * 1024, 20 * 1024 * 1024).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.operation(), "pmemblk_create");
+    assert_eq!(BlkPool::open_no_size(path).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(BlkPool::check(path, 0).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn options() {
+    let path = Path::new("/tmp/test-options.pmemblk");
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut options = BlkPool::options();
+    options.create(true).block_size(4 * 1024).size(20 * 1024 * 1024).mode(0o600);
+    {
+        let p = options.open(path).unwrap();
+        assert_eq!(p.block_size(), 4 * 1024);
+    }
+    assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+
+    // opens the existing pool
+    let p = options.open(path).unwrap();
+    assert_eq!(p.block_size(), 4 * 1024);
+    drop(p);
+
+    let err = options.create_new(true).open(path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(err.operation(), "pmemblk_create");
+    fs::remove_file(path).unwrap();
+
+    let err = BlkPool::options().block_size(4 * 1024).open(path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn options_without_size() {
+    let path = Path::new("/tmp/test-options_without_size.pmemblk");
+    fs::write(path, vec![0; 1024 * 1024]).unwrap();
+    let err = BlkPool::options().block_size(4 * 1024).create_new(true).open(path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.operation(), "pmemblk_create");
+    assert_eq!(BlkPool::options().block_size(4 * 1024).create(true).open(path).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    // left as it was
+    assert_eq!(fs::read(path).unwrap(), vec![0; 1024 * 1024]);
+    fs::remove_file(path).unwrap();
+}
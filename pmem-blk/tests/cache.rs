@@ -0,0 +1,135 @@
+extern crate pmem_blk;
+
+mod common;
+
+use ::std::io;
+use ::std::path::Path;
+use ::std::sync::Arc;
+use ::std::thread;
+
+use ::pmem_blk::{BlkPool, CachedPool};
+use ::pmem_blk::cache::CacheStats;
+
+use common::create_pool;
+
+fn create(path: &Path, capacity: usize) -> CachedPool {
+    let p = create_pool(path);
+    CachedPool::new(p, capacity)
+}
+
+#[test]
+fn hits_and_misses() {
+    let p = create(Path::new("/tmp/test-cache-hits_and_misses.pmemblk"), 8);
+    let mut buf = [0; 4 * 1024];
+    p.read(&mut buf, 1).unwrap();
+    p.read(&mut buf, 1).unwrap();
+    p.read(&mut buf, 2).unwrap();
+    assert_eq!(p.stats(), CacheStats { hits: 1, misses: 2 });
+
+    p.reset_stats();
+    assert_eq!(p.stats(), CacheStats::default());
+}
+
+#[test]
+fn write_through() {
+    let path = Path::new("/tmp/test-cache-write_through.pmemblk");
+    {
+        let p = create(path, 8);
+        let buf = [3; 4 * 1024];
+        p.write(&buf, 1).unwrap();
+
+        let mut out = [0; 4 * 1024];
+        p.read(&mut out, 1).unwrap();
+        assert_eq!(out[0], 3);
+        assert_eq!(p.stats(), CacheStats { hits: 1, misses: 0 });
+    }
+
+    let p = BlkPool::open_no_size(path).unwrap();
+    let mut out = [0; 4 * 1024];
+    p.read(&mut out, 1).unwrap();
+    assert_eq!(out[0], 3);
+}
+
+#[test]
+fn eviction() {
+    let p = create(Path::new("/tmp/test-cache-eviction.pmemblk"), 2);
+    let mut buf = [0; 4 * 1024];
+    p.read(&mut buf, 1).unwrap();
+    p.read(&mut buf, 2).unwrap();
+    // 1 becomes the most recently used
+    p.read(&mut buf, 1).unwrap();
+    // evicts 2
+    p.read(&mut buf, 3).unwrap();
+    assert_eq!(p.cached(), 2);
+
+    p.reset_stats();
+    p.read(&mut buf, 1).unwrap();
+    p.read(&mut buf, 2).unwrap();
+    assert_eq!(p.stats(), CacheStats { hits: 1, misses: 1 });
+}
+
+#[test]
+fn invalidate() {
+    let p = create(Path::new("/tmp/test-cache-invalidate.pmemblk"), 8);
+    let mut buf = [0; 4 * 1024];
+    p.read(&mut buf, 1).unwrap();
+
+    // bypass the cache
+    p.get_ref().write(&[9; 4 * 1024], 1).unwrap();
+    p.read(&mut buf, 1).unwrap();
+    assert_eq!(buf[0], 0);
+
+    p.invalidate(1);
+    p.read(&mut buf, 1).unwrap();
+    assert_eq!(buf[0], 9);
+
+    p.invalidate_all();
+    assert_eq!(p.cached(), 0);
+}
+
+#[test]
+fn disabled() {
+    let p = create(Path::new("/tmp/test-cache-disabled.pmemblk"), 0);
+    let mut buf = [0; 4 * 1024];
+    p.write(&buf, 1).unwrap();
+    p.read(&mut buf, 1).unwrap();
+    assert_eq!(p.cached(), 0);
+    assert_eq!(p.stats(), CacheStats { hits: 0, misses: 1 });
+}
+
+#[test]
+fn short_buffer() {
+    let p = create(Path::new("/tmp/test-cache-short_buffer.pmemblk"), 8);
+    p.write(&[5; 4 * 1024], 1).unwrap();
+
+    // a hit, and a miss
+    let mut buf = [0; 1024];
+    assert_eq!(p.read(&mut buf, 1).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(p.read(&mut buf, 2).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(p.write(&buf, 1).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(p.stats(), CacheStats::default());
+}
+
+#[test]
+fn concurrent_writes() {
+    let p = Arc::new(create(Path::new("/tmp/test-cache-concurrent_writes.pmemblk"), 8));
+    let mut writers = Vec::new();
+    for t in 1..5u8 {
+        let p = p.clone();
+        writers.push(thread::spawn(move || {
+            for _ in 0..100 {
+                p.write(&[t; 4 * 1024], 1).unwrap();
+            }
+        }));
+    }
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    // the cache agrees with the pool
+    let mut cached = [0; 4 * 1024];
+    let mut stored = [0; 4 * 1024];
+    p.read(&mut cached, 1).unwrap();
+    p.get_ref().read(&mut stored, 1).unwrap();
+    assert_eq!(cached[..], stored[..]);
+}
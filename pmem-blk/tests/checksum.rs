@@ -0,0 +1,149 @@
+extern crate pmem_blk;
+
+mod common;
+
+use ::std::io;
+use ::std::path::Path;
+use ::std::sync::Arc;
+use ::std::thread;
+
+use ::pmem_blk::{BlkPool, ChecksumPool};
+
+use common::create_pool;
+
+fn create(path: &Path) -> ChecksumPool {
+    let p = create_pool(path);
+    ChecksumPool::new(p).unwrap()
+}
+
+#[test]
+fn capacity() {
+    let p = create(Path::new("/tmp/test-checksum-capacity.pmemblk"));
+    assert!(p.capacity() > 0);
+    assert!(p.capacity() < p.get_ref().capacity());
+}
+
+#[test]
+fn read_write() {
+    let path = Path::new("/tmp/test-checksum-read_write.pmemblk");
+    {
+        let p = create(path);
+        let buf = [7; 4 * 1024];
+        p.write(&buf, 3).unwrap();
+    }
+
+    let p = ChecksumPool::new(BlkPool::open_no_size(path).unwrap()).unwrap();
+    let mut buf = [0; 4 * 1024];
+    p.read(&mut buf, 3).unwrap();
+    assert_eq!(buf[0], 7);
+    assert_eq!(buf[4095], 7);
+
+    // never written
+    p.read(&mut buf, 4).unwrap();
+    assert_eq!(buf[0], 0);
+}
+
+#[test]
+fn out_of_range() {
+    let p = create(Path::new("/tmp/test-checksum-out_of_range.pmemblk"));
+    let buf = [1; 4 * 1024];
+    let err = p.write(&buf, p.capacity() as i64).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn detect_corruption() {
+    let p = create(Path::new("/tmp/test-checksum-detect_corruption.pmemblk"));
+    let buf = [1; 4 * 1024];
+    p.write(&buf, 1).unwrap();
+    p.write(&buf, 2).unwrap();
+
+    // bypass the checksums
+    let bad = [2; 4 * 1024];
+    p.get_ref().write(&bad, 2).unwrap();
+
+    let mut out = [0; 4 * 1024];
+    p.read(&mut out, 1).unwrap();
+    let err = p.read(&mut out, 2).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn scrub() {
+    let p = create(Path::new("/tmp/test-checksum-scrub.pmemblk"));
+    let buf = [1; 4 * 1024];
+    p.write(&buf, 5).unwrap();
+    assert!(p.scrub(false).unwrap().is_clean());
+
+    let bad = [2; 4 * 1024];
+    p.get_ref().write(&bad, 5).unwrap();
+
+    let report = p.scrub(true).unwrap();
+    assert_eq!(report.checked, p.capacity());
+    assert_eq!(report.corrupt, vec![5]);
+
+    // the block is now marked as bad
+    let report = p.scrub(false).unwrap();
+    assert!(report.corrupt.is_empty());
+    assert_eq!(report.unreadable, vec![5]);
+
+    // writing it again clears the error
+    p.write(&buf, 5).unwrap();
+    assert!(p.scrub(false).unwrap().is_clean());
+}
+
+#[test]
+fn torn_write() {
+    let p = create(Path::new("/tmp/test-checksum-torn_write.pmemblk"));
+    let old = [1; 4 * 1024];
+    p.write(&old, 6).unwrap();
+
+    // a crash between the checksum and the data writes leaves the old contents
+    p.write(&[2; 4 * 1024], 6).unwrap();
+    p.get_ref().write(&old, 6).unwrap();
+
+    assert!(p.scrub(true).unwrap().is_clean());
+    let mut buf = [0; 4 * 1024];
+    p.read(&mut buf, 6).unwrap();
+    assert_eq!(buf[0], 1);
+
+    // and the next write replaces them as usual
+    p.write(&[3; 4 * 1024], 6).unwrap();
+    p.read(&mut buf, 6).unwrap();
+    assert_eq!(buf[0], 3);
+}
+
+#[test]
+fn scrub_in_background() {
+    let p = Arc::new(create(Path::new("/tmp/test-checksum-scrub_in_background.pmemblk")));
+    let buf = [1; 4 * 1024];
+    p.write(&buf, 0).unwrap();
+
+    let scrubber = {
+        let p = p.clone();
+        thread::spawn(move || p.scrub(false).unwrap())
+    };
+    assert!(scrubber.join().unwrap().is_clean());
+}
+
+#[test]
+fn scrub_while_writing() {
+    let p = Arc::new(create(Path::new("/tmp/test-checksum-scrub_while_writing.pmemblk")));
+    let writer = {
+        let p = p.clone();
+        thread::spawn(move || {
+            for i in 0..1000 {
+                let buf = [i as u8; 4 * 1024];
+                p.write(&buf, i % 8).unwrap();
+            }
+        })
+    };
+    let mut buf = [0; 4 * 1024];
+    while !writer.is_finished() {
+        let mut report = Default::default();
+        p.scrub_range(0, 8, true, &mut report).unwrap();
+        assert!(report.is_clean(), "{:?}", report);
+        p.read(&mut buf, 3).unwrap();
+    }
+    writer.join().unwrap();
+}
@@ -0,0 +1,20 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use ::std::fs;
+use ::std::path::Path;
+
+use ::pmem_blk::BlkPool;
+
+/// Removes what a previous run left at `path`
+pub fn clean(path: &Path) {
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+/// A fresh pool of 20 MiB with 4 KiB blocks at `path`
+pub fn create_pool(path: &Path) -> BlkPool {
+    clean(path);
+    BlkPool::create(path, 4 * 1024, 20 * 1024 * 1024).unwrap()
+}
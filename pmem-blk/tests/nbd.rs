@@ -0,0 +1,222 @@
+extern crate pmem_blk;
+
+mod common;
+
+use ::std::io::{Read, Write};
+use ::std::os::unix::net::UnixStream;
+use ::std::path::Path;
+use ::std::sync::Arc;
+use ::std::thread;
+
+use ::pmem_blk::{nbd, BlkPool};
+
+use common::create_pool;
+
+/// Minimal NBD client, just enough to drive the server
+struct Client {
+    stream: UnixStream,
+    size: u64,
+    handle: u64,
+}
+
+impl Client {
+    fn read_u16(&mut self) -> u16 {
+        let mut b = [0; 2];
+        self.stream.read_exact(&mut b).unwrap();
+        u16::from_be_bytes(b)
+    }
+
+    fn read_u32(&mut self) -> u32 {
+        let mut b = [0; 4];
+        self.stream.read_exact(&mut b).unwrap();
+        u32::from_be_bytes(b)
+    }
+
+    fn read_u64(&mut self) -> u64 {
+        let mut b = [0; 8];
+        self.stream.read_exact(&mut b).unwrap();
+        u64::from_be_bytes(b)
+    }
+
+    fn send_option(&mut self, option: u32, data: &[u8]) {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"IHAVEOPT");
+        buf.extend_from_slice(&option.to_be_bytes());
+        buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        buf.extend_from_slice(data);
+        self.stream.write_all(&buf).unwrap();
+    }
+
+    /// Reads an option reply, returns its type and payload
+    fn option_reply(&mut self, option: u32) -> (u32, Vec<u8>) {
+        assert_eq!(self.read_u64(), 0x0003_e889_0455_65a9);
+        assert_eq!(self.read_u32(), option);
+        let reply = self.read_u32();
+        let len = self.read_u32();
+        let mut data = vec![0; len as usize];
+        self.stream.read_exact(&mut data).unwrap();
+        (reply, data)
+    }
+
+    fn connect(stream: UnixStream) -> Client {
+        let mut client = Client { stream, size: 0, handle: 0 };
+        let mut magic = [0; 16];
+        client.stream.read_exact(&mut magic).unwrap();
+        assert_eq!(&magic, b"NBDMAGICIHAVEOPT");
+        let flags = client.read_u16();
+        assert_eq!(flags & 1, 1);
+        // fixed newstyle, no zeroes
+        client.stream.write_all(&3u32.to_be_bytes()).unwrap();
+
+        // NBD_OPT_GO, empty name, no info requests
+        client.send_option(7, &[0, 0, 0, 0, 0, 0]);
+        loop {
+            let (reply, data) = client.option_reply(7);
+            match reply {
+                // NBD_REP_INFO
+                3 => {
+                    if data[0..2] == [0, 0] {
+                        let mut size = [0; 8];
+                        size.copy_from_slice(&data[2..10]);
+                        client.size = u64::from_be_bytes(size);
+                    }
+                }
+                // NBD_REP_ACK
+                1 => break,
+                r => panic!("Unexpected reply {:#x}", r),
+            }
+        }
+        client
+    }
+
+    fn request(&mut self, command: u16, offset: u64, len: u32, data: &[u8]) -> u64 {
+        self.handle += 1;
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&0x2560_9513u32.to_be_bytes());
+        buf.extend_from_slice(&0u16.to_be_bytes());
+        buf.extend_from_slice(&command.to_be_bytes());
+        buf.extend_from_slice(&self.handle.to_be_bytes());
+        buf.extend_from_slice(&offset.to_be_bytes());
+        buf.extend_from_slice(&len.to_be_bytes());
+        buf.extend_from_slice(data);
+        self.stream.write_all(&buf).unwrap();
+        self.handle
+    }
+
+    /// Reads a simple reply, returns the error code
+    fn reply(&mut self, handle: u64) -> u32 {
+        assert_eq!(self.read_u32(), 0x6744_6698);
+        let error = self.read_u32();
+        assert_eq!(self.read_u64(), handle);
+        error
+    }
+
+    fn read(&mut self, offset: u64, len: u32) -> Result<Vec<u8>, u32> {
+        let handle = self.request(0, offset, len, &[]);
+        match self.reply(handle) {
+            0 => {
+                let mut data = vec![0; len as usize];
+                self.stream.read_exact(&mut data).unwrap();
+                Ok(data)
+            }
+            err => Err(err),
+        }
+    }
+
+    fn write(&mut self, offset: u64, data: &[u8]) -> u32 {
+        let handle = self.request(1, offset, data.len() as u32, data);
+        self.reply(handle)
+    }
+
+    fn flush(&mut self) -> u32 {
+        let handle = self.request(3, 0, 0, &[]);
+        self.reply(handle)
+    }
+
+    fn trim(&mut self, offset: u64, len: u32) -> u32 {
+        let handle = self.request(4, offset, len, &[]);
+        self.reply(handle)
+    }
+
+    fn disconnect(&mut self) { self.request(2, 0, 0, &[]); }
+}
+
+fn start(path: &Path) -> (Arc<BlkPool>, Client, thread::JoinHandle<()>) {
+    let pool = Arc::new(create_pool(path));
+    let (client, server) = UnixStream::pair().unwrap();
+    let handle = {
+        let pool = pool.clone();
+        thread::spawn(move || nbd::serve(&pool, server).unwrap())
+    };
+    (pool, Client::connect(client), handle)
+}
+
+#[test]
+fn handshake() {
+    let (pool, mut client, server) = start(Path::new("/tmp/test-nbd-handshake.pmemblk"));
+    assert_eq!(client.size, (pool.capacity() * pool.block_size()) as u64);
+    client.disconnect();
+    server.join().unwrap();
+}
+
+#[test]
+fn read_write() {
+    let (pool, mut client, server) = start(Path::new("/tmp/test-nbd-read_write.pmemblk"));
+
+    assert_eq!(client.write(4096, &[5; 8192]), 0);
+    assert_eq!(client.read(4096, 8192).unwrap(), vec![5; 8192]);
+    assert_eq!(client.flush(), 0);
+
+    let mut buf = [0; 4 * 1024];
+    pool.read(&mut buf, 2).unwrap();
+    assert_eq!(buf[0], 5);
+
+    client.disconnect();
+    server.join().unwrap();
+}
+
+#[test]
+fn unaligned() {
+    let (_pool, mut client, server) = start(Path::new("/tmp/test-nbd-unaligned.pmemblk"));
+
+    assert_eq!(client.write(4000, &[7; 200]), 0);
+    let data = client.read(3996, 208).unwrap();
+    assert_eq!(&data[..4], &[0; 4]);
+    assert_eq!(&data[4..204], &[7; 200][..]);
+    assert_eq!(&data[204..], &[0; 4]);
+
+    client.disconnect();
+    server.join().unwrap();
+}
+
+#[test]
+fn trim() {
+    let (pool, mut client, server) = start(Path::new("/tmp/test-nbd-trim.pmemblk"));
+
+    assert_eq!(client.write(0, &[1; 3 * 4096]), 0);
+    // only block 1 is fully covered
+    assert_eq!(client.trim(100, 2 * 4096), 0);
+
+    let mut buf = [0; 4 * 1024];
+    pool.read(&mut buf, 0).unwrap();
+    assert_eq!(buf[0], 1);
+    pool.read(&mut buf, 1).unwrap();
+    assert_eq!(buf[0], 0);
+    pool.read(&mut buf, 2).unwrap();
+    assert_eq!(buf[0], 1);
+
+    client.disconnect();
+    server.join().unwrap();
+}
+
+#[test]
+fn out_of_range() {
+    let (_pool, mut client, server) = start(Path::new("/tmp/test-nbd-out_of_range.pmemblk"));
+
+    let size = client.size;
+    assert_eq!(client.read(size, 4096).unwrap_err(), 22);
+    assert_eq!(client.write(size - 10, &[1; 20]), 28);
+
+    client.disconnect();
+    server.join().unwrap();
+}
@@ -0,0 +1,105 @@
+extern crate pmem_blk;
+
+mod common;
+
+use ::std::io;
+use ::std::path::Path;
+
+use ::pmem_blk::{BlkPool, RecordStore};
+use ::pmem_blk::records::RecordId;
+
+use common::create_pool;
+
+fn create(path: &Path) -> RecordStore {
+    let p = create_pool(path);
+    RecordStore::new(p).unwrap()
+}
+
+#[test]
+fn insert_get() {
+    let path = Path::new("/tmp/test-records-insert_get.pmemblk");
+    let (a, b) = {
+        let s = create(path);
+        let a = s.insert(b"hello").unwrap();
+        let b = s.insert(b"world!").unwrap();
+        assert_eq!(s.get(a).unwrap(), b"hello");
+        (a, b)
+    };
+
+    let s = RecordStore::new(BlkPool::open_no_size(path).unwrap()).unwrap();
+    assert_eq!(s.get(a).unwrap(), b"hello");
+    assert_eq!(s.get(b).unwrap(), b"world!");
+}
+
+#[test]
+fn record_id() {
+    let id = RecordId { page: 12345, slot: 17 };
+    assert_eq!(RecordId::from_u64(id.to_u64()), id);
+}
+
+#[test]
+fn update() {
+    let s = create(Path::new("/tmp/test-records-update.pmemblk"));
+    let a = s.insert(b"short").unwrap();
+    let b = s.insert(b"neighbour").unwrap();
+
+    s.update(a, b"a much longer record than before").unwrap();
+    assert_eq!(s.get(a).unwrap(), b"a much longer record than before");
+    s.update(a, b"").unwrap();
+    assert_eq!(s.get(a).unwrap(), b"");
+    assert_eq!(s.get(b).unwrap(), b"neighbour");
+}
+
+#[test]
+fn delete() {
+    let s = create(Path::new("/tmp/test-records-delete.pmemblk"));
+    let a = s.insert(b"gone").unwrap();
+    s.delete(a).unwrap();
+    assert_eq!(s.get(a).unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!(s.delete(a).unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!(s.update(a, b"x").unwrap_err().kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn too_large() {
+    let s = create(Path::new("/tmp/test-records-too_large.pmemblk"));
+    let data = vec![1; s.max_record_size() + 1];
+    assert_eq!(s.insert(&data).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+    let data = vec![1; s.max_record_size()];
+    let id = s.insert(&data).unwrap();
+    assert_eq!(s.get(id).unwrap(), data);
+}
+
+#[test]
+fn reuse_space() {
+    let s = create(Path::new("/tmp/test-records-reuse_space.pmemblk"));
+    let data = vec![1; 1000];
+    let ids: Vec<_> = (0..4).map(|_| s.insert(&data).unwrap()).collect();
+    assert!(ids.iter().all(|id| id.page == ids[0].page));
+
+    // frees room in the middle of the page, the next insert needs compaction
+    s.delete(ids[1]).unwrap();
+    s.delete(ids[2]).unwrap();
+    let id = s.insert(&vec![2; 1500]).unwrap();
+    assert_eq!(id.page, ids[0].page);
+    assert_eq!(s.get(ids[0]).unwrap(), data);
+    assert_eq!(s.get(ids[3]).unwrap(), data);
+    assert_eq!(s.get(id).unwrap(), vec![2; 1500]);
+}
+
+#[test]
+fn iter() {
+    let s = create(Path::new("/tmp/test-records-iter.pmemblk"));
+    let data = vec![1; 3000];
+    let a = s.insert(&data).unwrap();
+    let b = s.insert(&data).unwrap();
+    let c = s.insert(b"small").unwrap();
+    s.delete(a).unwrap();
+
+    let records: Vec<_> = s.iter().map(|r| r.unwrap()).collect();
+    let ids: Vec<_> = records.iter().map(|r| r.0).collect();
+    let mut expected = vec![b, c];
+    expected.sort();
+    assert_eq!(ids, expected);
+}
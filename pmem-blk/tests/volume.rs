@@ -0,0 +1,191 @@
+extern crate pmem_blk;
+
+mod common;
+
+use ::std::io;
+use ::std::path::Path;
+use ::std::sync::Arc;
+use ::std::thread;
+
+use ::pmem_blk::{BlkPool, BlkVolume};
+
+use common::clean;
+
+fn create(name: &str, poolsize: usize) -> BlkPool {
+    let path = format!("/tmp/test-volume-{}.pmemblk", name);
+    let path = Path::new(&path);
+    clean(path);
+    BlkPool::create(path, 4 * 1024, poolsize).unwrap()
+}
+
+#[test]
+fn mirror_capacity() {
+    let v = BlkVolume::mirror(vec![create("mirror_capacity-0", 20 * 1024 * 1024),
+                                   create("mirror_capacity-1", 40 * 1024 * 1024)])
+        .unwrap();
+    assert_eq!(v.capacity(), v.members()[0].capacity());
+}
+
+#[test]
+fn stripe_capacity() {
+    let v = BlkVolume::stripe(vec![create("stripe_capacity-0", 20 * 1024 * 1024),
+                                   create("stripe_capacity-1", 20 * 1024 * 1024)])
+        .unwrap();
+    assert_eq!(v.capacity(), 2 * v.members()[0].capacity());
+}
+
+#[test]
+fn different_block_sizes() {
+    let path = Path::new("/tmp/test-volume-different_block_sizes.pmemblk");
+    clean(path);
+    let other = BlkPool::create(path, 8 * 1024, 20 * 1024 * 1024).unwrap();
+    let err = BlkVolume::mirror(vec![create("different_block_sizes", 20 * 1024 * 1024), other]).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn mirror_read_write() {
+    let v = BlkVolume::mirror(vec![create("mirror_read_write-0", 20 * 1024 * 1024),
+                                   create("mirror_read_write-1", 20 * 1024 * 1024)])
+        .unwrap();
+    v.write(&[4; 4 * 1024], 7).unwrap();
+
+    let mut buf = [0; 4 * 1024];
+    for member in v.members() {
+        member.read(&mut buf, 7).unwrap();
+        assert_eq!(buf[0], 4);
+    }
+    for _ in 0..4 {
+        buf = [0; 4 * 1024];
+        v.read(&mut buf, 7).unwrap();
+        assert_eq!(buf[0], 4);
+    }
+}
+
+#[test]
+fn mirror_read_error() {
+    let v = BlkVolume::mirror(vec![create("mirror_read_error-0", 20 * 1024 * 1024),
+                                   create("mirror_read_error-1", 20 * 1024 * 1024)])
+        .unwrap();
+    v.write(&[4; 4 * 1024], 7).unwrap();
+    v.members()[0].set_error(7).unwrap();
+
+    let mut buf = [0; 4 * 1024];
+    for _ in 0..2 {
+        v.read(&mut buf, 7).unwrap();
+        assert_eq!(buf[0], 4);
+    }
+    // the bad copy was rewritten
+    v.members()[0].read(&mut buf, 7).unwrap();
+}
+
+#[test]
+fn stripe_read_write() {
+    let v = BlkVolume::stripe(vec![create("stripe_read_write-0", 20 * 1024 * 1024),
+                                   create("stripe_read_write-1", 20 * 1024 * 1024)])
+        .unwrap();
+    v.write(&[1; 4 * 1024], 4).unwrap();
+    v.write(&[2; 4 * 1024], 5).unwrap();
+
+    let mut buf = [0; 4 * 1024];
+    v.members()[0].read(&mut buf, 2).unwrap();
+    assert_eq!(buf[0], 1);
+    v.members()[1].read(&mut buf, 2).unwrap();
+    assert_eq!(buf[0], 2);
+
+    v.read(&mut buf, 4).unwrap();
+    assert_eq!(buf[0], 1);
+    assert!(v.repair().is_err());
+}
+
+#[test]
+fn repair() {
+    let v = BlkVolume::mirror(vec![create("repair-0", 20 * 1024 * 1024),
+                                   create("repair-1", 20 * 1024 * 1024),
+                                   create("repair-2", 20 * 1024 * 1024)])
+        .unwrap();
+    v.write(&[1; 4 * 1024], 3).unwrap();
+    v.members()[0].write(&[9; 4 * 1024], 3).unwrap();
+
+    let report = v.repair().unwrap();
+    assert_eq!(report.checked, v.capacity());
+    assert_eq!(report.repaired, vec![3]);
+
+    let mut buf = [0; 4 * 1024];
+    v.members()[0].read(&mut buf, 3).unwrap();
+    assert_eq!(buf[0], 1);
+    assert!(v.repair().unwrap().repaired.is_empty());
+}
+
+#[test]
+fn repair_unreadable() {
+    let v = BlkVolume::mirror(vec![create("repair_unreadable-0", 20 * 1024 * 1024),
+                                   create("repair_unreadable-1", 20 * 1024 * 1024)])
+        .unwrap();
+    v.write(&[1; 4 * 1024], 3).unwrap();
+    v.write(&[2; 4 * 1024], 5).unwrap();
+    v.members()[1].write(&[9; 4 * 1024], 5).unwrap();
+    for member in v.members() {
+        member.set_error(3).unwrap();
+    }
+
+    // keeps going after the block lost on every member
+    let report = v.repair().unwrap();
+    assert_eq!(report.checked, v.capacity());
+    assert_eq!(report.unreadable, vec![3]);
+    assert_eq!(report.repaired, vec![5]);
+}
+
+#[test]
+fn read_repair_while_writing() {
+    let v = Arc::new(BlkVolume::mirror(vec![create("read_repair_while_writing-0", 20 * 1024 * 1024),
+                                            create("read_repair_while_writing-1", 20 * 1024 * 1024)])
+        .unwrap());
+    v.write(&[0; 4 * 1024], 2).unwrap();
+    let writer = {
+        let v = v.clone();
+        thread::spawn(move || {
+            for i in 1..200 {
+                v.write(&[i as u8; 4 * 1024], 2).unwrap();
+            }
+        })
+    };
+    let mut buf = [0; 4 * 1024];
+    while !writer.is_finished() {
+        let _ = v.members()[0].set_error(2);
+        v.read(&mut buf, 2).unwrap();
+    }
+    writer.join().unwrap();
+
+    // no repair put an older copy back on the first member, it is either unreadable or up to date
+    v.members()[1].read(&mut buf, 2).unwrap();
+    assert_eq!(buf[0], 199);
+    if v.members()[0].read(&mut buf, 2).is_ok() {
+        assert_eq!(buf[0], 199);
+    }
+}
+
+#[test]
+fn replace_and_resync() {
+    let mut v = BlkVolume::mirror(vec![create("replace_and_resync-0", 20 * 1024 * 1024),
+                                       create("replace_and_resync-1", 20 * 1024 * 1024)])
+        .unwrap();
+    v.write(&[6; 4 * 1024], 10).unwrap();
+
+    v.write(&[7; 4 * 1024], 12).unwrap();
+    v.members()[0].set_error(3).unwrap();
+
+    // keeps going after the block lost on the remaining member
+    let _old = v.replace(1, create("replace_and_resync-2", 20 * 1024 * 1024)).unwrap();
+    let report = v.resync(1).unwrap();
+    assert_eq!(report.checked, v.capacity());
+    assert_eq!(report.unreadable, vec![3]);
+    assert_eq!(report.repaired, vec![10, 12]);
+    assert!(report.failed.is_empty());
+
+    let mut buf = [0; 4 * 1024];
+    v.members()[1].read(&mut buf, 10).unwrap();
+    assert_eq!(buf[0], 6);
+    v.members()[1].read(&mut buf, 12).unwrap();
+    assert_eq!(buf[0], 7);
+}
@@ -0,0 +1,63 @@
+//! Prints the records of a log written by a `RecordLog`, such as the ones of a `pmem_log::Sink`
+//!
+//! ```text
+//! pmem-log-read <pool>
+//! pmem-log-read <directory>
+//! ```
+//!
+//! A directory is read as a `SegmentedLog`. Records that are not valid UTF-8 are printed escaped.
+
+extern crate pmem_log;
+
+use ::std::env;
+use ::std::ffi::OsString;
+use ::std::io::{self, Write};
+use ::std::ops::ControlFlow;
+use ::std::path::Path;
+use ::std::process;
+
+use ::pmem_log::record::Records;
+use ::pmem_log::{RecordLog, SegmentedLog};
+
+const USAGE: &str = "Usage: pmem-log-read (<pool> | <directory>)";
+
+fn print(records: Records<'_>) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for record in records {
+        let record = record?;
+        match ::std::str::from_utf8(&record) {
+            Ok(line) => writeln!(out, "{}", line)?,
+            Err(_) => writeln!(out, "{}", record.escape_ascii())?,
+        }
+    }
+    out.flush()
+}
+
+fn run(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        let log = SegmentedLog::open(path)?;
+        let mut data = Vec::new();
+        log.walk(0, |_, chunk| {
+            data.extend_from_slice(chunk);
+            ControlFlow::<io::Error>::Continue(())
+        })?;
+        print(Records::new(&data))
+    } else {
+        let log = RecordLog::open(path)?;
+        print(log.records())
+    }
+}
+
+fn main() {
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    if args.len() != 1 {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    if let Err(err) = run(Path::new(&args[0])) {
+        eprintln!("pmem-log-read: {}", err);
+        process::exit(1);
+    }
+}
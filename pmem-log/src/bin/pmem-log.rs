@@ -0,0 +1,78 @@
+//! Exports and imports pmem logs, see the `pmem_log::export` module for the format
+//!
+//! ```text
+//! pmem-log export <pool> <file>
+//! pmem-log import <file> <pool> [<size>]
+//! ```
+//!
+//! `export` writes to the standard output when `<file>` is `-`.
+//! `import` creates the pool, by default big enough for the capacity of the exported log.
+
+extern crate pmem_log;
+
+use ::std::env;
+use ::std::ffi::{OsStr, OsString};
+use ::std::fs::File;
+use ::std::io::{self, BufReader, BufWriter};
+use ::std::path::Path;
+use ::std::process;
+
+use ::pmem_log::export::ExportHeader;
+use ::pmem_log::Log;
+
+const USAGE: &str = "Usage: pmem-log export <pool> <file>\n       pmem-log import <file> <pool> [<size>]";
+
+/// Room for the pool metadata on top of the capacity
+const POOL_OVERHEAD: u64 = 1024 * 1024;
+
+/// Smallest pool libpmemlog accepts
+const MIN_POOL_SIZE: u64 = 2 * 1024 * 1024;
+
+fn export(pool: &Path, file: &OsStr) -> io::Result<()> {
+    let log = Log::open(pool)?;
+    let len = if file == "-" {
+        let stdout = io::stdout();
+        log.export(BufWriter::new(stdout.lock()))?
+    } else {
+        log.export(BufWriter::new(File::create(file)?))?
+    };
+    eprintln!("Exported {} bytes", len);
+    Ok(())
+}
+
+fn import(file: &Path, pool: &Path, size: Option<&OsStr>) -> io::Result<()> {
+    let size = match size {
+        Some(size) => {
+            size.to_str().and_then(|s| s.parse().ok()).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput,
+                                   format!("Invalid size {}", size.to_string_lossy()))
+                })?
+        }
+        None => {
+            let header = ExportHeader::read(&mut File::open(file)?)?;
+            (header.capacity.max(header.len) + POOL_OVERHEAD).max(MIN_POOL_SIZE) as usize
+        }
+    };
+    let log = Log::import(BufReader::new(File::open(file)?), pool, size)?;
+    eprintln!("Imported {} bytes", log.len());
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    let command = args.first().and_then(|c| c.to_str());
+    let r = match (command, args.get(1..).unwrap_or(&[])) {
+        (Some("export"), [pool, file]) => export(Path::new(pool), file),
+        (Some("import"), [file, pool]) => import(Path::new(file), Path::new(pool), None),
+        (Some("import"), [file, pool, size]) => import(Path::new(file), Path::new(pool), Some(size)),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = r {
+        eprintln!("pmem-log: {}", err);
+        process::exit(1);
+    }
+}
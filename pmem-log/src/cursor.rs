@@ -0,0 +1,261 @@
+//! Persisted consumer offsets
+//!
+//! A reader processing a log at-least-once needs to remember how far it got.
+//! `Cursors` keeps named offsets durably in a small side file mapped with `pmem::PersistentMap`,
+//! typically placed next to the log or inside the directory of a `SegmentedLog`.
+//!
+//! A consumer calls `Cursor::resume()` to find where to start and `Cursor::commit()` once it has processed
+//! everything before an offset. The log can then be truncated up to the slowest committed cursor,
+//! `Cursors::truncate_front()` and `Cursors::delete_before()` refuse to go any further.
+//!
+//! The file starts with a 64 byte header followed by fixed size slots of 64 bytes,
+//! each one holding the committed offset (8 bytes), the length of the name (1 byte) and the name.
+//! A slot with an empty name is free.
+
+use ::std::io;
+use ::std::path::Path;
+use ::std::ptr;
+use ::std::sync::Mutex;
+
+use ::pmem::{self, pmap::PersistentMap};
+
+use log::Log;
+use segmented::SegmentedLog;
+
+const MAGIC: &[u8; 4] = b"PCUR";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 64;
+const SLOT_SIZE: usize = 64;
+const NAME_LEN_OFFSET: usize = 8;
+const NAME_OFFSET: usize = 9;
+
+/// Longest name a cursor can have, in bytes
+pub const MAX_NAME_LEN: usize = SLOT_SIZE - NAME_OFFSET;
+
+/// Durable store of named consumer offsets
+pub struct Cursors {
+    map: Mutex<PersistentMap>,
+    slots: usize,
+}
+
+fn persist(map: &PersistentMap, start: usize, len: usize) -> Result<(), io::Error> {
+    pmem::msync_unsized(&map[start..start + len]).map_err(io::Error::from)
+}
+
+fn slot_offset(slot: usize) -> usize { HEADER_SIZE + slot * SLOT_SIZE }
+
+fn slot_name(map: &PersistentMap, slot: usize) -> &[u8] {
+    let base = slot_offset(slot);
+    let len = map[base + NAME_LEN_OFFSET] as usize;
+    &map[base + NAME_OFFSET..base + NAME_OFFSET + len.min(MAX_NAME_LEN)]
+}
+
+fn read_offset(map: &PersistentMap, slot: usize) -> u64 {
+    let p = map[slot_offset(slot)..].as_ptr() as *const u64;
+    // slots are 8 byte aligned within the page aligned mapping
+    u64::from_le(unsafe { ptr::read_volatile(p) })
+}
+
+/// Stores `offset` with a single aligned 8 byte write, so a crash leaves either the old or the new value
+fn write_offset(map: &mut PersistentMap, slot: usize, offset: u64) -> Result<(), io::Error> {
+    let p = map[slot_offset(slot)..].as_mut_ptr() as *mut u64;
+    unsafe { ptr::write_volatile(p, offset.to_le()) };
+    persist(map, slot_offset(slot), 8)
+}
+
+impl Cursors {
+    /// Creates a file at `path` able to hold up to `max_cursors` cursors
+    pub fn create<P: AsRef<Path>>(path: P, max_cursors: usize) -> Result<Self, io::Error> {
+        Cursors::create_mode(path, max_cursors, 0o666)
+    }
+
+    /// Like `create()`, with the permissions `mode` for the file, the process umask applies
+    ///
+    /// Pass the mode the log was created with, see `LogOptions::mode()`, to keep the cursors as private as it.
+    pub fn create_mode<P: AsRef<Path>>(path: P, max_cursors: usize, mode: u32) -> Result<Self, io::Error> {
+        let len = slot_offset(max_cursors);
+        let mut map = PersistentMap::create(path, len, false, mode)?;
+        for b in map[..len].iter_mut() {
+            *b = 0;
+        }
+        persist(&map, HEADER_SIZE, len - HEADER_SIZE)?;
+
+        map[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        map[8..16].copy_from_slice(&(max_cursors as u64).to_le_bytes());
+        persist(&map, 0, HEADER_SIZE)?;
+        // the magic goes last, a crash before it leaves an invalid file
+        map[..4].copy_from_slice(MAGIC);
+        persist(&map, 0, 4)?;
+
+        Ok(Cursors { map: Mutex::new(map), slots: max_cursors })
+    }
+
+    /// Opens the file at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        let map = PersistentMap::open(path)?;
+        if map.len() < HEADER_SIZE || &map[..4] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a cursors file"));
+        }
+        let mut version = [0; 4];
+        version.copy_from_slice(&map[4..8]);
+        let version = u32::from_le_bytes(version);
+        if version != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Unsupported cursors file version {}", version)));
+        }
+        let mut slots = [0; 8];
+        slots.copy_from_slice(&map[8..16]);
+        let slots = u64::from_le_bytes(slots) as usize;
+        if slots.checked_mul(SLOT_SIZE).is_none_or(|len| map.len() - HEADER_SIZE < len) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Cursors file is too short for {} cursors", slots)));
+        }
+        Ok(Cursors { map: Mutex::new(map), slots })
+    }
+
+    /// Opens the file at `path`, creating it with room for `max_cursors` cursors if it does not exist
+    pub fn open_or_create<P: AsRef<Path>>(path: P, max_cursors: usize) -> Result<Self, io::Error> {
+        if path.as_ref().exists() {
+            Cursors::open(path)
+        } else {
+            Cursors::create(path, max_cursors)
+        }
+    }
+
+    /// The maximum number of cursors
+    pub fn capacity(&self) -> usize { self.slots }
+
+    fn find(&self, map: &PersistentMap, name: &[u8]) -> Option<usize> {
+        (0..self.slots).find(|&slot| !slot_name(map, slot).is_empty() && slot_name(map, slot) == name)
+    }
+
+    /// The cursor called `name`, registering it at offset 0 if it does not exist yet
+    pub fn cursor(&self, name: &str) -> Result<Cursor<'_>, io::Error> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Cursor names must be 1 to {} bytes long", MAX_NAME_LEN)));
+        }
+        let mut map = self.map.lock().unwrap();
+        if let Some(slot) = self.find(&map, name.as_bytes()) {
+            return Ok(Cursor { cursors: self, slot });
+        }
+        let slot = match (0..self.slots).find(|&slot| slot_name(&map, slot).is_empty()) {
+            Some(slot) => slot,
+            None => {
+                return Err(io::Error::other(format!("All the {} cursors are in use", self.slots)));
+            }
+        };
+
+        let base = slot_offset(slot);
+        write_offset(&mut map, slot, 0)?;
+        map[base + NAME_OFFSET..base + NAME_OFFSET + name.len()].copy_from_slice(name.as_bytes());
+        persist(&map, base + NAME_OFFSET, name.len())?;
+        // setting the length makes the slot in use
+        map[base + NAME_LEN_OFFSET] = name.len() as u8;
+        persist(&map, base + NAME_LEN_OFFSET, 1)?;
+        Ok(Cursor { cursors: self, slot })
+    }
+
+    /// Forgets the cursor called `name`, returns whether it existed
+    ///
+    /// A consumer that is gone for good must be removed, or it holds back truncation forever.
+    pub fn remove(&mut self, name: &str) -> Result<bool, io::Error> {
+        let mut map = self.map.lock().unwrap();
+        match self.find(&map, name.as_bytes()) {
+            Some(slot) => {
+                let at = slot_offset(slot) + NAME_LEN_OFFSET;
+                map[at] = 0;
+                persist(&map, at, 1)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// The names and committed offsets of all the cursors
+    pub fn list(&self) -> Vec<(String, u64)> {
+        let map = self.map.lock().unwrap();
+        self.in_use(&map)
+            .map(|slot| (String::from_utf8_lossy(slot_name(&map, slot)).into_owned(), read_offset(&map, slot)))
+            .collect()
+    }
+
+    fn in_use<'m>(&self, map: &'m PersistentMap) -> impl Iterator<Item = usize> + 'm {
+        (0..self.slots).filter(move |&slot| !slot_name(map, slot).is_empty())
+    }
+
+    /// The offset of the slowest cursor, `None` if there are no cursors
+    pub fn min_offset(&self) -> Option<u64> { self.list().into_iter().map(|(_, offset)| offset).min() }
+
+    /// Checks every cursor has committed up to `upto`, with the lock held so none can be added meanwhile
+    fn check_truncate(&self, map: &PersistentMap, upto: u64) -> Result<(), io::Error> {
+        match self.in_use(map).map(|slot| read_offset(map, slot)).min() {
+            Some(min) if min < upto => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                   format!("Offset {} is past the slowest committed cursor at {}", upto, min)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Discards the data before offset `upto` from `log`, see `Log::truncate_front()`
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if a cursor has not committed up to `upto` yet.
+    /// As the log offsets shift down by `upto`, so do the committed offsets.
+    /// They are moved before truncating the log, so a crash in between makes consumers see some data again
+    /// but never skip any. No cursor can be added nor committed until the log is truncated.
+    pub fn truncate_front(&self, log: &mut Log, upto: usize) -> Result<(), io::Error> {
+        let mut map = self.map.lock().unwrap();
+        self.check_truncate(&map, upto as u64)?;
+        if upto > log.len() {
+            // let the log report the bad offset, before any cursor is moved
+            return log.truncate_front(upto);
+        }
+        let mut shifted = Vec::new();
+        for slot in self.in_use(&map) {
+            let offset = read_offset(&map, slot).checked_sub(upto as u64).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput,
+                               format!("Offset {} is past the cursor at {}", upto, read_offset(&map, slot)))
+            })?;
+            shifted.push((slot, offset));
+        }
+        for (slot, offset) in shifted {
+            write_offset(&mut map, slot, offset)?;
+        }
+        log.truncate_front(upto)
+    }
+
+    /// Deletes the segments of `log` holding only data before `retain`, see `SegmentedLog::delete_before()`
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if a cursor has not committed up to `retain` yet.
+    pub fn delete_before(&self, log: &mut SegmentedLog, retain: u64) -> Result<usize, io::Error> {
+        let map = self.map.lock().unwrap();
+        self.check_truncate(&map, retain)?;
+        log.delete_before(retain)
+    }
+}
+
+/// Named consumer offset, created by `Cursors::cursor()`
+pub struct Cursor<'a> {
+    cursors: &'a Cursors,
+    slot: usize,
+}
+
+impl<'a> Cursor<'a> {
+    /// The name of this cursor
+    pub fn name(&self) -> String {
+        let map = self.cursors.map.lock().unwrap();
+        String::from_utf8_lossy(slot_name(&map, self.slot)).into_owned()
+    }
+
+    /// The offset to resume reading from, the last one committed
+    pub fn resume(&self) -> u64 { read_offset(&self.cursors.map.lock().unwrap(), self.slot) }
+
+    /// Durably records that everything before `offset` has been processed
+    ///
+    /// The write is **atomic**, a crash leaves either the previous offset or this one.
+    pub fn commit(&self, offset: u64) -> Result<(), io::Error> {
+        let mut map = self.cursors.map.lock().unwrap();
+        write_offset(&mut map, self.slot, offset)
+    }
+}
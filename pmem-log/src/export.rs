@@ -0,0 +1,152 @@
+//! Portable export of log contents
+//!
+//! A pool file is only usable with **libpmemlog**, on a machine where it can be mapped.
+//! `Log::export()` writes the contents of a log to any `io::Write` in a simple, documented format,
+//! for backups or to inspect it with standard tools, and `Log::import()` creates a log back from it.
+//!
+//! An export is a 32 byte header followed by the data in blocks, all the integers being little-endian:
+//!
+//! ```text
+//! | magic: "PMEMLOGX" | version: u32 | header crc: u32 | data length: u64 | capacity: u64 |
+//! | block: [u8; 65536] | crc: u32 | ... | last block: [u8; 1..=65536] | crc: u32 |
+//! ```
+//!
+//! - `version` is 1.
+//! - `header crc` is the CRC-32 (IEEE 802.3) of the other 28 bytes of the header.
+//! - `capacity` is the capacity of the exported log, informative only.
+//! - Every block but the last one is `BLOCK_SIZE` bytes long, the last one holds the remaining bytes.
+//!   Each block is followed by its CRC-32.
+//!   There is no block at all for an empty log.
+
+use ::std::fs;
+use ::std::io::{self, Read, Write};
+use ::std::path::Path;
+
+use ::pmem::crc::{crc32, crc32_parts};
+
+use log::Log;
+
+/// Magic bytes starting every export
+pub const MAGIC: &[u8; 8] = b"PMEMLOGX";
+
+/// Current version of the format
+pub const VERSION: u32 = 1;
+
+/// Size in bytes of the header
+pub const HEADER_SIZE: usize = 32;
+
+/// Size in bytes of a full data block
+pub const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Header of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportHeader {
+    pub version: u32,
+    /// Number of bytes of log data
+    pub len: u64,
+    /// Capacity of the exported log
+    pub capacity: u64,
+}
+
+impl ExportHeader {
+    fn to_bytes(self) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&self.version.to_le_bytes());
+        header[16..24].copy_from_slice(&self.len.to_le_bytes());
+        header[24..32].copy_from_slice(&self.capacity.to_le_bytes());
+        let crc = crc32_parts(&[&header[..12], &header[16..]]);
+        header[12..16].copy_from_slice(&crc.to_le_bytes());
+        header
+    }
+
+    /// Reads and validates the header of an export
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, io::Error> {
+        let mut header = [0; HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid("Not a log export".to_string()));
+        }
+        let u32_at = |at: usize| {
+            let mut b = [0; 4];
+            b.copy_from_slice(&header[at..at + 4]);
+            u32::from_le_bytes(b)
+        };
+        let u64_at = |at: usize| {
+            let mut b = [0; 8];
+            b.copy_from_slice(&header[at..at + 8]);
+            u64::from_le_bytes(b)
+        };
+        let version = u32_at(8);
+        let crc = u32_at(12);
+        if version != VERSION {
+            return Err(invalid(format!("Unsupported log export version {}", version)));
+        }
+        if crc32_parts(&[&header[..12], &header[16..]]) != crc {
+            return Err(invalid("Corrupt log export header".to_string()));
+        }
+        Ok(ExportHeader { version, len: u64_at(16), capacity: u64_at(24) })
+    }
+}
+
+fn invalid(msg: String) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, msg) }
+
+impl Log {
+    /// Writes the contents of the log to `writer` in the export format, returning the number of data bytes
+    ///
+    /// See the `export` module for the format.
+    pub fn export<W: Write>(&self, mut writer: W) -> Result<u64, io::Error> {
+        let data = self.contents();
+        let header = ExportHeader { version: VERSION, len: data.len() as u64, capacity: self.capacity() as u64 };
+        writer.write_all(&header.to_bytes())?;
+        for block in data.chunks(BLOCK_SIZE) {
+            writer.write_all(block)?;
+            writer.write_all(&crc32(block).to_le_bytes())?;
+        }
+        writer.flush()?;
+        Ok(data.len() as u64)
+    }
+
+    /// Creates a log of `size` bytes at `path` holding the data exported to `reader`
+    ///
+    /// Every block is checked against its CRC before being appended. On failure, including a corrupt
+    /// or truncated export, the new pool file is removed and an `ErrorKind::InvalidData` or
+    /// `ErrorKind::UnexpectedEof` error returned.
+    pub fn import<R: Read, P: AsRef<Path>>(mut reader: R, path: P, size: usize) -> Result<Log, io::Error> {
+        let header = ExportHeader::read(&mut reader)?;
+        let mut log = Log::create(path.as_ref(), size)?;
+        match log.import_blocks(&mut reader, header.len) {
+            Ok(()) => Ok(log),
+            Err(err) => {
+                drop(log);
+                let _ = fs::remove_file(path);
+                Err(err)
+            }
+        }
+    }
+
+    fn import_blocks<R: Read>(&mut self, reader: &mut R, len: u64) -> Result<(), io::Error> {
+        if len > self.capacity() as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("The export holds {} bytes, more than the capacity of {}",
+                                              len,
+                                              self.capacity())));
+        }
+        let mut block = vec![0; BLOCK_SIZE];
+        let mut left = len;
+        let mut offset = 0;
+        while left > 0 {
+            let n = left.min(BLOCK_SIZE as u64) as usize;
+            reader.read_exact(&mut block[..n])?;
+            let mut crc = [0; 4];
+            reader.read_exact(&mut crc)?;
+            if crc32(&block[..n]) != u32::from_le_bytes(crc) {
+                return Err(invalid(format!("Corrupt block at offset {} of the log export", offset)));
+            }
+            self.append(&block[..n])?;
+            left -= n as u64;
+            offset += n as u64;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//! Sparse record index
+//!
+//! Finding the Nth record of a `RecordLog` means walking all the frames before it.
+//! An `IndexedLog` keeps the byte offset of every `interval`-th record in a companion file mapped with
+//! `pmem::PersistentMap`, so `seek_record()` jumps to the closest indexed record and only walks the rest.
+//!
+//! The index file starts with a 64 byte header followed by the offsets, as little-endian `u64`s.
+//! Every append adds the offsets it owes before bumping the count of valid offsets with a single 8 byte write,
+//! so a crash leaves the index behind the log at worst; opening it catches up from the last indexed record.
+//! An index that does not belong to the log anymore, for example after a `Log::truncate_front()` or a
+//! `Log::rewind()`, is detected through checksums of the first and the last indexed records and the length
+//! of the log indexed so far, and rebuilt from scratch, as is a missing one.
+
+use ::std::borrow::Cow;
+use ::std::fs;
+use ::std::io;
+use ::std::path::{Path, PathBuf};
+use ::std::ptr;
+
+use ::pmem::{self, crc::crc32, pmap::PersistentMap};
+
+use record::{self, RecordLog, Records};
+
+const MAGIC: &[u8; 4] = b"PIDX";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 64;
+const INTERVAL_OFFSET: usize = 8;
+const COUNT_OFFSET: usize = 16;
+const CHECK_OFFSET: usize = 24;
+const LENGTH_OFFSET: usize = 32;
+const LAST_CHECK_OFFSET: usize = 40;
+
+/// Default number of records between two indexed ones
+pub const DEFAULT_INTERVAL: usize = 64;
+
+fn persist(map: &PersistentMap, start: usize, len: usize) -> Result<(), io::Error> {
+    pmem::msync_unsized(&map[start..start + len]).map_err(io::Error::from)
+}
+
+fn read_u64(map: &PersistentMap, at: usize) -> u64 {
+    // all the fields are 8 byte aligned within the page aligned mapping
+    u64::from_le(unsafe { ptr::read_volatile(map[at..].as_ptr() as *const u64) })
+}
+
+/// Stores `value` with a single aligned 8 byte write and persists it
+fn write_u64(map: &mut PersistentMap, at: usize, value: u64) -> Result<(), io::Error> {
+    unsafe { ptr::write_volatile(map[at..].as_mut_ptr() as *mut u64, value.to_le()) };
+    persist(map, at, 8)
+}
+
+fn entry_offset(i: usize) -> usize { HEADER_SIZE + 8 * i }
+
+/// Fingerprint of the whole frame at offset `at` of `data`, tells whether an index belongs to a log
+///
+/// Records often share a prefix, like a fixed header or the start of a JSON object,
+/// only the whole frame tells them apart.
+fn frame_check(data: &[u8], at: usize) -> u64 {
+    let len = match Records::at(data, at).next_frame() {
+        Some(Ok((_, entry))) => record::HEADER_SIZE + entry.len(),
+        _ => 0,
+    };
+    crc32(&data[at..at + len]) as u64
+}
+
+/// `RecordLog` with a sparse index of its records
+pub struct IndexedLog {
+    log: RecordLog,
+    index: PersistentMap,
+    index_path: PathBuf,
+    interval: usize,
+    /// Number of records in the log
+    records: usize,
+}
+
+impl IndexedLog {
+    /// Indexes `log` in the file at `index_path`, every `interval` records
+    ///
+    /// The index is created if it does not exist, and rebuilt if it is stale, damaged or uses another interval.
+    pub fn open<P: AsRef<Path>>(log: RecordLog, index_path: P, interval: usize) -> Result<Self, io::Error> {
+        if interval == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "The index interval must be at least 1"));
+        }
+        let index_path = index_path.as_ref().to_path_buf();
+        let index = match IndexedLog::open_index(&log, &index_path, interval) {
+            Some(index) => index,
+            None => IndexedLog::create_index(&log, &index_path, interval)?,
+        };
+        let mut indexed = IndexedLog { log, index, index_path, interval, records: 0 };
+        indexed.catch_up()?;
+        Ok(indexed)
+    }
+
+    /// The number of offsets an index for `log` may need
+    fn max_entries(log: &RecordLog, interval: usize) -> usize {
+        log.capacity() / (record::HEADER_SIZE * interval) + 1
+    }
+
+    /// Opens an existing index, `None` if it is missing or does not match the log
+    fn open_index(log: &RecordLog, path: &Path, interval: usize) -> Option<PersistentMap> {
+        if !path.exists() {
+            return None;
+        }
+        let index = PersistentMap::open(path).ok()?;
+        if index.len() < HEADER_SIZE || &index[..4] != MAGIC {
+            return None;
+        }
+        let mut version = [0; 4];
+        version.copy_from_slice(&index[4..8]);
+        if u32::from_le_bytes(version) != VERSION || read_u64(&index, INTERVAL_OFFSET) != interval as u64 {
+            return None;
+        }
+        let count = read_u64(&index, COUNT_OFFSET) as usize;
+        if entry_offset(IndexedLog::max_entries(log, interval)) > index.len() {
+            return None;
+        }
+        if count > 0 {
+            let data = log.get_ref().contents();
+            let last = read_u64(&index, entry_offset(count - 1)) as usize;
+            if read_u64(&index, LENGTH_OFFSET) as usize > data.len() || last > data.len() {
+                return None;
+            }
+            if read_u64(&index, CHECK_OFFSET) != frame_check(data, 0) ||
+               read_u64(&index, LAST_CHECK_OFFSET) != frame_check(data, last) {
+                return None;
+            }
+        }
+        Some(index)
+    }
+
+    /// Creates an empty index, replacing any existing file
+    fn create_index(log: &RecordLog, path: &Path, interval: usize) -> Result<PersistentMap, io::Error> {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        let len = entry_offset(IndexedLog::max_entries(log, interval));
+        let mut index = PersistentMap::create(path, len, true, 0o666)?;
+        for b in index[..HEADER_SIZE].iter_mut() {
+            *b = 0;
+        }
+        index[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        index[INTERVAL_OFFSET..INTERVAL_OFFSET + 8].copy_from_slice(&(interval as u64).to_le_bytes());
+        persist(&index, 0, HEADER_SIZE)?;
+        // the magic goes last, a crash before it leaves an invalid file
+        index[..4].copy_from_slice(MAGIC);
+        persist(&index, 0, 4)?;
+        Ok(index)
+    }
+
+    /// Indexes the records past the last indexed one and counts them
+    fn catch_up(&mut self) -> Result<(), io::Error> {
+        let count = read_u64(&self.index, COUNT_OFFSET) as usize;
+        let (mut n, pos) = match count {
+            0 => (0, 0),
+            _ => ((count - 1) * self.interval, read_u64(&self.index, entry_offset(count - 1)) as usize),
+        };
+
+        let data = self.log.get_ref().contents();
+        let mut offsets = Vec::new();
+        let mut frames = Records::at(data, pos);
+        loop {
+            let offset = frames.offset();
+            match frames.next_frame() {
+                Some(Ok(_)) => {}
+                // a torn tail is not a record
+                Some(Err(_)) | None => break,
+            }
+            if n % self.interval == 0 && n / self.interval >= count {
+                offsets.push(offset as u64);
+            }
+            n += 1;
+        }
+        let end = frames.offset();
+        if count == 0 && n > 0 {
+            let check = frame_check(data, 0);
+            write_u64(&mut self.index, CHECK_OFFSET, check)?;
+        }
+        // A crash between these writes leaves checks that do not match, the index is rebuilt then
+        if let Some(&last) = offsets.last() {
+            let check = frame_check(data, last as usize);
+            write_u64(&mut self.index, LAST_CHECK_OFFSET, check)?;
+        }
+        self.push_offsets(count, &offsets)?;
+        write_u64(&mut self.index, LENGTH_OFFSET, end as u64)?;
+        self.records = n;
+        Ok(())
+    }
+
+    /// Stores `offsets` after the first `count` ones, then makes them valid
+    fn push_offsets(&mut self, count: usize, offsets: &[u64]) -> Result<(), io::Error> {
+        if offsets.is_empty() {
+            return Ok(());
+        }
+        for (i, &offset) in offsets.iter().enumerate() {
+            let at = entry_offset(count + i);
+            self.index[at..at + 8].copy_from_slice(&offset.to_le_bytes());
+        }
+        persist(&self.index, entry_offset(count), 8 * offsets.len())?;
+        write_u64(&mut self.index, COUNT_OFFSET, (count + offsets.len()) as u64)
+    }
+
+    /// The indexed log
+    pub fn get_ref(&self) -> &RecordLog { &self.log }
+
+    /// Unwraps this `IndexedLog`, returning the log
+    pub fn into_inner(self) -> RecordLog { self.log }
+
+    /// The path of the index file
+    pub fn index_path(&self) -> &Path { &self.index_path }
+
+    /// The number of records between two indexed ones
+    pub fn interval(&self) -> usize { self.interval }
+
+    /// The number of records in the log
+    pub fn record_count(&self) -> usize { self.records }
+
+    /// Appends a record, see `RecordLog::append()`
+    pub fn append<T: AsRef<[u8]>>(&mut self, entry: T) -> Result<(), io::Error> { self.append_many(&[entry]) }
+
+    /// Appends several records with a single atomic append, see `RecordLog::append_many()`
+    pub fn append_many<T: AsRef<[u8]>>(&mut self, entries: &[T]) -> Result<(), io::Error> {
+        self.log.append_many(entries)?;
+        self.catch_up()
+    }
+
+    /// Iterates over the records starting at record number `n`, counting from 0
+    ///
+    /// Only the records between the closest indexed one and `n` are walked.
+    /// Fails with `ErrorKind::InvalidInput` if the log has fewer than `n` records.
+    pub fn seek_record(&self, n: usize) -> Result<Records<'_>, io::Error> {
+        if n > self.records {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Record {} is past the end of the log ({} records)",
+                                              n,
+                                              self.records)));
+        }
+        let count = read_u64(&self.index, COUNT_OFFSET) as usize;
+        let i = (n / self.interval).min(count.saturating_sub(1));
+        let pos = if count == 0 { 0 } else { read_u64(&self.index, entry_offset(i)) as usize };
+
+        let mut frames = self.log.records_at(pos);
+        for _ in i * self.interval..n {
+            frames.next_frame();
+        }
+        Ok(frames)
+    }
+
+    /// The record number `n`, `None` past the end of the log
+    pub fn get(&self, n: usize) -> Option<Result<Cow<'_, [u8]>, io::Error>> {
+        if n >= self.records {
+            return None;
+        }
+        self.seek_record(n).ok()?.next()
+    }
+}
@@ -5,9 +5,37 @@
 //! >
 //! > The official **libpmemlog** documentation can be found at: [http://pmem.io/nvml/libpmemlog/](http://pmem.io/nvml/libpmemlog/)
 
+extern crate pmem;
 extern crate pmemlog_sys;
 extern crate libc;
+#[cfg(feature = "log")]
+extern crate log as log_crate;
+#[cfg(feature = "tracing")]
+extern crate tracing_core;
+#[cfg(feature = "tracing")]
+extern crate tracing_subscriber;
+#[cfg(feature = "compression")]
+extern crate zstd;
 
+pub mod cursor;
+pub mod export;
+pub mod index;
 pub mod log;
+pub mod record;
+pub mod ring;
+pub mod segmented;
+pub mod shared;
+pub mod sink;
+pub mod wal;
+pub mod writer;
 
-pub use log::Log;
+pub use cursor::{Cursor, Cursors};
+pub use index::IndexedLog;
+pub use log::{Log, LogOptions};
+pub use record::RecordLog;
+pub use ring::RingLog;
+pub use segmented::SegmentedLog;
+pub use shared::{SharedLog, Subscription};
+pub use sink::Sink;
+pub use wal::{Lsn, Wal};
+pub use writer::LogWriter;
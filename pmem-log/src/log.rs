@@ -1,60 +1,126 @@
-use ::std::ffi::CString;
-use ::std::path::Path;
+use ::std::any::Any;
+use ::std::ffi::CStr;
+use ::std::fs;
+use ::std::path::{Path, PathBuf};
 use ::std::io;
+use ::std::ops::{ControlFlow, Range};
+use ::std::panic::{self, AssertUnwindSafe};
 
 use ::libc::iovec;
 use ::libc::{size_t, mode_t};
 use ::libc::{c_void, c_int};
 
+use ::pmem::error::{Error, path_to_cstring};
 use pmemlog_sys::{self as ffi, PMEMlogpool};
 
+fn errormsg() -> Option<String> {
+    unsafe {
+        let reason_p = ffi::pmemlog_errormsg();
+        if !reason_p.is_null() {
+            CStr::from_ptr(reason_p).to_owned().into_string().ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// Captures the error of the failed call `operation` on the pool at `path`
+fn error(operation: &'static str, path: &Path) -> Error {
+    Error::last_os_error(operation).with_message(errormsg()).with_path(path)
+}
+
 pub struct Log {
     inner: *mut PMEMlogpool,
+    path: PathBuf,
+}
+
+// libpmemlog is thread-safe, all the operations on a pool can be called concurrently.
+unsafe impl Send for Log {}
+unsafe impl Sync for Log {}
+
+/// State shared with the `visit_log` trampoline during a `walk()`
+struct Walk<F, E> {
+    callback: F,
+    result: Result<(), E>,
+    panic: Option<Box<dyn Any + Send>>,
 }
 
-extern "C" fn visit_log<F>(buf: *const c_void, len: size_t, arg: *mut c_void) -> c_int
-    where F: Fn(&[u8]) -> Option<()>
+extern "C" fn visit_log<F, E>(buf: *const c_void, len: size_t, arg: *mut c_void) -> c_int
+    where F: FnMut(&[u8]) -> ControlFlow<E>
 {
-    let callback = arg as *const F;
-    let buf = buf as *mut u8;
-    unsafe {
-        let item = ::std::slice::from_raw_parts(buf, len);
-        match (*callback)(item) {
-            Some(()) => 1,
-            None => 0,
+    let walk = unsafe { &mut *(arg as *mut Walk<F, E>) };
+    let item = if len == 0 {
+        &[]
+    } else {
+        unsafe { ::std::slice::from_raw_parts(buf as *const u8, len) }
+    };
+    // A panic must not unwind across the FFI boundary, it is resumed once `pmemlog_walk` returns
+    match panic::catch_unwind(AssertUnwindSafe(|| (walk.callback)(item))) {
+        Ok(ControlFlow::Continue(())) => 1,
+        Ok(ControlFlow::Break(err)) => {
+            walk.result = Err(err);
+            0
+        }
+        Err(payload) => {
+            walk.panic = Some(payload);
+            0
         }
     }
 }
 
 impl Log {
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
-        let path = CString::new(path.as_ref().to_str().unwrap()).unwrap();
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let cpath = path_to_cstring("pmemlog_open", path)?;
 
-        let objpool = unsafe { ffi::pmemlog_open(path.as_ptr()) };
+        let objpool = unsafe { ffi::pmemlog_open(cpath.as_ptr()) };
 
         if objpool.is_null() {
-            Err(io::Error::last_os_error())
+            Err(error("pmemlog_open", path))
         } else {
-            Ok(Log { inner: objpool })
+            Ok(Log { inner: objpool, path: path.to_path_buf() })
         }
     }
 
-    pub fn create<P: AsRef<Path>>(path: P, size: usize) -> Result<Self, io::Error> {
-        let path = path.as_ref().to_str().unwrap();
-        let path = CString::new(path).unwrap();
+    pub fn create<P: AsRef<Path>>(path: P, size: usize) -> Result<Self, Error> {
+        Log::create_mode(path.as_ref(), size, 0o666)
+    }
 
-        let mode = 0o666;
+    fn create_mode(path: &Path, size: usize, mode: u32) -> Result<Self, Error> {
+        let cpath = path_to_cstring("pmemlog_create", path)?;
 
-        let objpool = unsafe { ffi::pmemlog_create(path.as_ptr(), size as size_t, mode as mode_t) };
+        let objpool = unsafe { ffi::pmemlog_create(cpath.as_ptr(), size as size_t, mode as mode_t) };
 
         if objpool.is_null() {
-            Err(io::Error::last_os_error())
+            Err(error("pmemlog_create", path))
         } else {
-            Ok(Log { inner: objpool })
+            Ok(Log { inner: objpool, path: path.to_path_buf() })
+        }
+    }
+
+    /// Options to open or create a log, see `LogOptions`
+    pub fn options() -> LogOptions { LogOptions::new() }
+
+    /// Check consistency of the log pool at `path`, which must not be open
+    pub fn check<P: AsRef<Path>>(path: P) -> Result<bool, Error> {
+        let path = path.as_ref();
+        let cpath = path_to_cstring("pmemlog_check", path)?;
+
+        let r = unsafe { ffi::pmemlog_check(cpath.as_ptr()) };
+        match r {
+            1 => Ok(true),
+            0 => Ok(false),
+            -1 => Err(error("pmemlog_check", path)),
+            r => {
+                Err(Error::new(io::ErrorKind::Other,
+                               "pmemlog_check",
+                               format!("Invalid return value, expected 1, 0 or -1 but received {}", r))
+                        .with_path(path))
+            }
         }
     }
 
-    pub fn append<T: AsRef<[u8]>>(&mut self, entry: T) -> Result<(), io::Error> {
+    pub fn append<T: AsRef<[u8]>>(&mut self, entry: T) -> Result<(), Error> {
         let buf = entry.as_ref();
         let len = buf.len();
 
@@ -66,11 +132,11 @@ impl Log {
         if r == 0 {
             Ok(())
         } else {
-            Err(io::Error::last_os_error())
+            Err(error("pmemlog_append", &self.path))
         }
     }
 
-    pub fn append_many<T: AsRef<[u8]>>(&mut self, entries: &[T]) -> Result<(), io::Error> {
+    pub fn append_many<T: AsRef<[u8]>>(&mut self, entries: &[T]) -> Result<(), Error> {
         let count = entries.len();
         let mut io_vecs = Vec::with_capacity(count);
         for entry in entries {
@@ -87,7 +153,7 @@ impl Log {
         if r == 0 {
             Ok(())
         } else {
-            Err(io::Error::last_os_error())
+            Err(error("pmemlog_appendv", &self.path))
         }
     }
 
@@ -95,16 +161,274 @@ impl Log {
         unsafe { ffi::pmemlog_tell(self.inner) as usize }
     }
 
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
     pub fn capacity(&self) -> usize { unsafe { ffi::pmemlog_nbyte(self.inner) as usize } }
 
-    pub fn walk<F>(&self, chunk_size: usize, callback: F)
-        where F: Fn(&[u8]) -> Option<()>
+    /// The path of the pool file backing this log
+    pub fn path(&self) -> &Path { &self.path }
+
+    /// Discards all the data in the log, the next append will start at offset 0
+    pub fn rewind(&mut self) {
+        unsafe { ffi::pmemlog_rewind(self.inner) }
+    }
+
+    /// Discards the data before offset `upto`, keeping the rest
+    ///
+    /// The retained data is copied into a fresh pool of the same size and permissions,
+    /// which then atomically replaces the pool file using `rename(2)`.
+    /// A crash leaves either the old or the new log, never a mixture of both.
+    /// On failure the temporary pool is removed and the log is left as it was.
+    ///
+    /// After this call the data previously at offset `upto` is at offset 0.
+    pub fn truncate_front(&mut self, upto: usize) -> Result<(), io::Error> {
+        let len = self.len();
+        if upto > len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Offset {} is past the end of the log ({} bytes)", upto, len)));
+        }
+        if upto == 0 {
+            return Ok(());
+        }
+        if upto == len {
+            self.rewind();
+            return Ok(());
+        }
+
+        self.rewrite(upto..len)
+    }
+
+    /// Discards the data from offset `len` on, keeping the first `len` bytes
+    ///
+    /// Like `truncate_front()`, the data is copied into a fresh pool which atomically replaces the pool file.
+    pub fn truncate(&mut self, len: usize) -> Result<(), io::Error> {
+        self.check_offset(len)?;
+        if len == self.len() {
+            return Ok(());
+        }
+        if len == 0 {
+            self.rewind();
+            return Ok(());
+        }
+        self.rewrite(0..len)
+    }
+
+    /// Replaces the pool with a fresh one holding only the data in `range`
+    ///
+    /// The fresh pool gets the permissions of the current one. On failure the temporary pool is removed
+    /// and the log is left as it was.
+    fn rewrite(&mut self, range: Range<usize>) -> Result<(), io::Error> {
+        let metadata = fs::metadata(&self.path)?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        if tmp.exists() {
+            fs::remove_file(&tmp)?;
+        }
+
+        let fresh = Log::create(&tmp, metadata.len() as usize)
+            .map_err(io::Error::from)
+            .and_then(|mut fresh| {
+                // the fresh pool gets the permissions of the current one before any data goes in
+                fs::set_permissions(&tmp, metadata.permissions())?;
+                fresh.append(&self.contents()[range])?;
+                fs::rename(&tmp, &self.path)?;
+                Ok(fresh)
+            });
+        let mut fresh = match fresh {
+            Ok(fresh) => fresh,
+            Err(err) => {
+                let _ = fs::remove_file(&tmp);
+                return Err(err);
+            }
+        };
+        // the fresh pool stays open across the rename, the handle never points to the unlinked pool
+        fresh.path = self.path.clone();
+        *self = fresh;
+
+        // make the rename durable
+        let dir = match self.path.parent() {
+            Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        fs::File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    /// The whole contents of the log
+    ///
+    /// The slice points into the pool, it stays valid as long as the log is not modified.
+    pub(crate) fn contents(&self) -> &[u8] {
+        let mut chunk: (*const u8, usize) = (::std::ptr::null(), 0);
+        let _: Result<(), ()> = self.walk(0, |data| {
+            chunk = (data.as_ptr(), data.len());
+            ControlFlow::Continue(())
+        });
+        match chunk {
+            (_, 0) => &[],
+            (ptr, len) => unsafe { ::std::slice::from_raw_parts(ptr, len) },
+        }
+    }
+
+    fn check_offset(&self, offset: usize) -> Result<(), io::Error> {
+        let len = self.len();
+        if offset > len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Offset {} is past the end of the log ({} bytes)", offset, len)));
+        }
+        Ok(())
+    }
+
+    /// Reads the data at `offset` into `buf`, returning the number of bytes read
+    ///
+    /// Fewer bytes than `buf.len()` are read when the log ends before, 0 at the end of the log.
+    /// Fails with `ErrorKind::InvalidInput` if `offset` is past the end of the log.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, io::Error> {
+        self.check_offset(offset)?;
+        let data = &self.contents()[offset..];
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    /// Walks through the log like `walk()`, starting at `offset` instead of the beginning
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if `offset` is past the end of the log.
+    pub fn walk_from<F, E>(&self, offset: usize, chunk_size: usize, mut callback: F) -> Result<(), E>
+        where F: FnMut(&[u8]) -> ControlFlow<E>,
+              E: From<io::Error>
+    {
+        self.check_offset(offset)?;
+        let data = &self.contents()[offset..];
+        if data.is_empty() {
+            return Ok(());
+        }
+        let chunk_size = if chunk_size == 0 { data.len() } else { chunk_size };
+        for chunk in data.chunks(chunk_size) {
+            if let ControlFlow::Break(err) = callback(chunk) {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Walks through the log from the beginning, calling `callback` with chunks of at most `chunk_size` bytes
+    ///
+    /// A `chunk_size` of 0 hands the whole log to `callback` in a single call.
+    /// The walk stops early when `callback` returns `ControlFlow::Break`, its value is returned as the error.
+    ///
+    /// The chunks are cut at arbitrary positions, they do not follow the boundaries of the appended entries.
+    /// See `RecordLog` to read back the entries.
+    ///
+    /// If `callback` panics the walk is stopped and the panic is resumed once the log has been released.
+    pub fn walk<F, E>(&self, chunk_size: usize, callback: F) -> Result<(), E>
+        where F: FnMut(&[u8]) -> ControlFlow<E>
     {
+        let mut walk = Walk { callback, result: Ok(()), panic: None };
         unsafe {
-            let arg = &callback as *const _ as *mut c_void;
-            ffi::pmemlog_walk(self.inner, chunk_size as size_t, visit_log::<F>, arg)
+            let arg = &mut walk as *mut Walk<F, E> as *mut c_void;
+            ffi::pmemlog_walk(self.inner, chunk_size as size_t, visit_log::<F, E>, arg)
         };
+        if let Some(payload) = walk.panic {
+            panic::resume_unwind(payload);
+        }
+        walk.result
+    }
+}
+
+/// Options to open or create a `Log`, in the style of `std::fs::OpenOptions`
+///
+/// ```no_run
+/// # use pmem_log::LogOptions;
+/// let mut options = LogOptions::new();
+/// options.create(true).size(64 * 1024 * 1024).mode(0o600);
+/// let log = options.open("/mnt/pmem/log").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    size: usize,
+    mode: u32,
+    create: bool,
+    create_new: bool,
+}
+
+impl LogOptions {
+    /// Options to open an existing log
+    pub fn new() -> Self { LogOptions { size: 0, mode: 0o666, create: false, create_new: false } }
+
+    /// The size of the pool to create, required with `create()` or `create_new()`
+    pub fn size(&mut self, size: usize) -> &mut Self {
+        self.size = size;
+        self
     }
+
+    /// The permissions of a created pool file, `0o666` by default, the process umask applies
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Creates the pool if it does not exist, opens it otherwise
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Creates the pool, failing if the file already exists
+    ///
+    /// Takes precedence over `create()`.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Opens the log at `path` with these options
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<Log, Error> {
+        let path = path.as_ref();
+        // with a size of 0 the library formats an existing file in place instead of failing
+        if (self.create || self.create_new) && self.size == 0 {
+            let msg = "The size of the pool to create is 0";
+            return Err(Error::new(io::ErrorKind::InvalidInput, "pmemlog_create", msg).with_path(path));
+        }
+        if self.create_new {
+            return Log::create_mode(path, self.size, self.mode);
+        }
+        match Log::open(path) {
+            Err(ref err) if self.create && err.kind() == io::ErrorKind::NotFound => {
+                match Log::create_mode(path, self.size, self.mode) {
+                    // created by someone else in the meantime
+                    Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => Log::open(path),
+                    r => r,
+                }
+            }
+            r => r,
+        }
+    }
+}
+
+impl Default for LogOptions {
+    fn default() -> Self { LogOptions::new() }
+}
+
+/// Each `write()` is a single atomic append of the whole buffer, see `Log::append()`,
+/// so a `write_all()` is atomic too. `write_vectored()` appends all the buffers with one atomic append.
+/// Code writing a value with several calls, like most serializers, makes several appends though.
+///
+/// The appends are durable once `write()` returns, `flush()` does nothing.
+/// Use `LogWriter` to group several small writes into one append.
+impl io::Write for Log {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.append(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let bufs: Vec<&[u8]> = bufs.iter().map(|b| &**b).collect();
+        self.append_many(&bufs)?;
+        Ok(bufs.iter().map(|b| b.len()).sum())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
 }
 
 impl Drop for Log {
@@ -0,0 +1,319 @@
+//! Record-framed logs
+//!
+//! `Log::walk` hands out the log in arbitrary chunks, so the entries appended to it cannot be told apart.
+//! A `RecordLog` prefixes every entry with its length, the entries can then be read back
+//! as the units they were written in with `records()`.
+//!
+//! Each frame is a 4 byte little-endian length followed by the entry itself.
+//! The top bit of the length flags a compressed entry, so an entry can be at most `MAX_RECORD_SIZE` bytes.
+//!
+//! With the `compression` feature, `set_compression()` makes a `RecordLog` compress its entries with zstd,
+//! optionally with a dictionary. Each record carries its flag, so compressed and plain records coexist,
+//! and `records()` reads them all back decompressed.
+
+use ::std::borrow::Cow;
+use ::std::io;
+use ::std::path::Path;
+#[cfg(feature = "compression")]
+use ::std::sync::Mutex;
+
+#[cfg(feature = "compression")]
+use ::zstd;
+
+use log::Log;
+
+/// Size in bytes of the frame header
+pub const HEADER_SIZE: usize = 4;
+
+/// Largest entry a frame can hold
+pub const MAX_RECORD_SIZE: usize = (1 << 31) - 1;
+
+/// Flag of the compressed entries in the frame header
+const COMPRESSED: u32 = 1 << 31;
+
+/// How a `RecordLog` compresses its entries
+#[cfg(feature = "compression")]
+#[derive(Debug, Clone)]
+pub struct Compression {
+    level: i32,
+    dictionary: Vec<u8>,
+    min_size: usize,
+}
+
+#[cfg(feature = "compression")]
+impl Compression {
+    /// Compresses with zstd at `level`, 0 meaning the zstd default
+    pub fn new(level: i32) -> Self { Compression { level, dictionary: Vec::new(), min_size: 64 } }
+
+    /// Compresses with `dictionary`, as trained by `zstd --train`
+    ///
+    /// Small similar entries compress much better with a dictionary.
+    /// The records can only be read back with the same dictionary.
+    pub fn dictionary(mut self, dictionary: Vec<u8>) -> Self {
+        self.dictionary = dictionary;
+        self
+    }
+
+    /// Leaves the entries smaller than `min_size` bytes uncompressed, 64 by default
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+}
+
+/// Log of length-prefixed records
+pub struct RecordLog {
+    log: Log,
+    #[cfg(feature = "compression")]
+    compression: Option<(Compression, zstd::bulk::Compressor<'static>)>,
+    #[cfg(feature = "compression")]
+    decompressor: Decompressor,
+}
+
+/// The zstd context decompressing the records of a log, built on first use
+#[cfg(feature = "compression")]
+type Decompressor = Mutex<Option<zstd::bulk::Decompressor<'static>>>;
+
+/// Builds the frame header for an entry of `len` bytes
+pub(crate) fn header(len: usize) -> Result<[u8; HEADER_SIZE], io::Error> {
+    if len > MAX_RECORD_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("Record of {} bytes is larger than the maximum of {}",
+                                          len,
+                                          MAX_RECORD_SIZE)));
+    }
+    Ok((len as u32).to_le_bytes())
+}
+
+#[cfg(feature = "compression")]
+fn compressed_header(len: usize) -> Result<[u8; HEADER_SIZE], io::Error> {
+    header(len).map(|h| (u32::from_le_bytes(h) | COMPRESSED).to_le_bytes())
+}
+
+impl RecordLog {
+    /// Wraps an existing log
+    ///
+    /// The log must only contain frames written by a `RecordLog`.
+    pub fn new(log: Log) -> Self {
+        RecordLog {
+            log,
+            #[cfg(feature = "compression")]
+            compression: None,
+            #[cfg(feature = "compression")]
+            decompressor: Mutex::new(None),
+        }
+    }
+
+    /// Opens the log at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> { Ok(RecordLog::new(Log::open(path)?)) }
+
+    /// Creates a log of `size` bytes at `path`
+    pub fn create<P: AsRef<Path>>(path: P, size: usize) -> Result<Self, io::Error> {
+        Ok(RecordLog::new(Log::create(path, size)?))
+    }
+
+    /// The underlying log
+    pub fn get_ref(&self) -> &Log { &self.log }
+
+    /// Unwraps this `RecordLog`, returning the underlying log
+    pub fn into_inner(self) -> Log { self.log }
+
+    /// Compresses the entries appended from now on, or stops compressing them with `None`
+    ///
+    /// The zstd contexts are built here, once, with the dictionary if any.
+    /// Reading the records back needs the same dictionary.
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&mut self, compression: Option<Compression>) -> Result<(), io::Error> {
+        let (compression, decompressor) = match compression {
+            Some(compression) => {
+                let compressor = zstd::bulk::Compressor::with_dictionary(compression.level, &compression.dictionary)?;
+                let decompressor = zstd::bulk::Decompressor::with_dictionary(&compression.dictionary)?;
+                (Some((compression, compressor)), Some(decompressor))
+            }
+            None => (None, None),
+        };
+        self.compression = compression;
+        *self.decompressor.get_mut().unwrap_or_else(|e| e.into_inner()) = decompressor;
+        Ok(())
+    }
+
+    /// How the entries are compressed, if they are
+    #[cfg(feature = "compression")]
+    pub fn compression(&self) -> Option<&Compression> { self.compression.as_ref().map(|c| &c.0) }
+
+    /// Appends a record
+    ///
+    /// The frame header and the entry are written with a single atomic append.
+    pub fn append<T: AsRef<[u8]>>(&mut self, entry: T) -> Result<(), io::Error> { self.append_many(&[entry]) }
+
+    /// Appends several records with a single atomic append
+    pub fn append_many<T: AsRef<[u8]>>(&mut self, entries: &[T]) -> Result<(), io::Error> {
+        #[cfg(feature = "compression")]
+        {
+            if let Some((ref compression, ref mut compressor)) = self.compression {
+                let mut frames = Vec::with_capacity(entries.len());
+                for entry in entries {
+                    let entry = entry.as_ref();
+                    let compressed = if entry.len() < compression.min_size {
+                        None
+                    } else {
+                        Some(compressor.compress(entry)?).filter(|c| c.len() < entry.len())
+                    };
+                    frames.push(match compressed {
+                        Some(compressed) => (compressed_header(compressed.len())?, Cow::Owned(compressed)),
+                        None => (header(entry.len())?, Cow::Borrowed(entry)),
+                    });
+                }
+                let mut bufs: Vec<&[u8]> = Vec::with_capacity(2 * frames.len());
+                for (header, entry) in &frames {
+                    bufs.push(&header[..]);
+                    bufs.push(entry);
+                }
+                return Ok(self.log.append_many(&bufs)?);
+            }
+        }
+
+        let mut headers = Vec::with_capacity(entries.len());
+        for entry in entries {
+            headers.push(header(entry.as_ref().len())?);
+        }
+        let mut bufs: Vec<&[u8]> = Vec::with_capacity(2 * entries.len());
+        for (header, entry) in headers.iter().zip(entries) {
+            bufs.push(&header[..]);
+            bufs.push(entry.as_ref());
+        }
+        Ok(self.log.append_many(&bufs)?)
+    }
+
+    /// The number of bytes in the log, including the frame headers
+    pub fn len(&self) -> usize { self.log.len() }
+
+    /// Whether the log holds no records
+    pub fn is_empty(&self) -> bool { self.log.len() == 0 }
+
+    /// The capacity of the log in bytes
+    pub fn capacity(&self) -> usize { self.log.capacity() }
+
+    /// Iterates over the records, oldest first
+    ///
+    /// Plain records are borrowed straight from the pool, compressed ones are decompressed.
+    /// If a record fails to decompress, for example without the right dictionary,
+    /// the iterator yields an `ErrorKind::InvalidData` error for it and moves on to the next one.
+    pub fn records(&self) -> Records<'_> { self.records_at(0) }
+
+    /// Iterates over the records from offset `pos`, which must be the start of a frame
+    pub(crate) fn records_at(&self, pos: usize) -> Records<'_> {
+        Records {
+            #[cfg(feature = "compression")]
+            decompressor: Some(&self.decompressor),
+            ..Records::at(self.log.contents(), pos)
+        }
+    }
+}
+
+/// Iterator over the records of a `RecordLog`, created by `RecordLog::records()`
+///
+/// If the log ends with an incomplete frame, the iterator yields an `ErrorKind::InvalidData` error and stops.
+/// A record that does not decompress is yielded as an `ErrorKind::InvalidData` error too,
+/// but the iteration goes on.
+pub struct Records<'a> {
+    data: &'a [u8],
+    pos: usize,
+    done: bool,
+    #[cfg(feature = "compression")]
+    decompressor: Option<&'a Decompressor>,
+}
+
+impl<'a> Records<'a> {
+    /// Iterates over the frames in `data`, as read from a log written by a `RecordLog`
+    ///
+    /// Compressed records are decompressed without a dictionary.
+    pub fn new(data: &'a [u8]) -> Self { Records::at(data, 0) }
+
+    /// Offset in the log of the next frame
+    pub fn offset(&self) -> usize { self.pos }
+
+    fn torn<T>(&mut self) -> Option<Result<T, io::Error>> {
+        self.done = true;
+        Some(Err(io::Error::new(io::ErrorKind::InvalidData,
+                                format!("Torn frame at offset {}, {} trailing bytes",
+                                        self.pos,
+                                        self.data.len() - self.pos))))
+    }
+}
+
+impl<'a> Records<'a> {
+    /// Iterates over the frames in `data` from offset `pos`, which must be the start of a frame
+    pub(crate) fn at(data: &'a [u8], pos: usize) -> Self {
+        Records {
+            data,
+            pos,
+            done: false,
+            #[cfg(feature = "compression")]
+            decompressor: None,
+        }
+    }
+
+    /// The next frame, with whether its entry is compressed
+    pub(crate) fn next_frame(&mut self) -> Option<Result<(bool, &'a [u8]), io::Error>> {
+        if self.done || self.pos == self.data.len() {
+            return None;
+        }
+        let rest = &self.data[self.pos..];
+        if rest.len() < HEADER_SIZE {
+            return self.torn();
+        }
+        let mut header = [0; HEADER_SIZE];
+        header.copy_from_slice(&rest[..HEADER_SIZE]);
+        let header = u32::from_le_bytes(header);
+        let len = (header & !COMPRESSED) as usize;
+        if rest.len() - HEADER_SIZE < len {
+            return self.torn();
+        }
+        self.pos += HEADER_SIZE + len;
+        Some(Ok((header & COMPRESSED != 0, &rest[HEADER_SIZE..HEADER_SIZE + len])))
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Cow<'a, [u8]>, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.pos;
+        Some(match self.next_frame()? {
+            Ok((false, entry)) => Ok(Cow::Borrowed(entry)),
+            Ok((true, data)) => {
+                self.decompress(data).map(Cow::Owned).map_err(|err| {
+                    io::Error::new(io::ErrorKind::InvalidData,
+                                   format!("Record at offset {} does not decompress: {}", offset, err))
+                })
+            }
+            Err(err) => Err(err),
+        })
+    }
+}
+
+impl<'a> Records<'a> {
+    #[cfg(feature = "compression")]
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let capacity = match zstd::zstd_safe::get_frame_content_size(data) {
+            Ok(Some(size)) if size <= MAX_RECORD_SIZE as u64 => size as usize,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown decompressed size")),
+        };
+        match self.decompressor {
+            Some(shared) => {
+                let mut decompressor = shared.lock().unwrap_or_else(|e| e.into_inner());
+                if decompressor.is_none() {
+                    *decompressor = Some(zstd::bulk::Decompressor::new()?);
+                }
+                decompressor.as_mut().unwrap().decompress(data, capacity)
+            }
+            None => zstd::bulk::Decompressor::new()?.decompress(data, capacity),
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn decompress(&self, _data: &[u8]) -> Result<Vec<u8>, io::Error> {
+        Err(io::Error::other("compressed, and the `compression` feature is disabled"))
+    }
+}
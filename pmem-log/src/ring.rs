@@ -0,0 +1,225 @@
+//! Circular logs
+//!
+//! A `Log` refuses appends once it is full. A `RingLog` overwrites its oldest entries instead,
+//! so it always holds the most recent ones: a flight recorder whose last events survive a crash.
+//!
+//! The ring lives in a file mapped with `pmem::PersistentMap`: a 64 byte header followed by the data area.
+//! The header holds the `head` and `tail` of the ring as ever growing logical offsets,
+//! the position of a byte in the data area being its offset modulo the capacity.
+//! Each entry is framed as a 4 byte little-endian length and a CRC-32 of the entry, followed by the entry,
+//! and frames wrap around the end of the data area.
+//!
+//! An append first moves `head` past the entries it is about to overwrite, then writes the frame and
+//! finally moves `tail` over it. Each step is persisted before the next one and `head` and `tail` are
+//! updated with single 8 byte writes, so a crash at any point leaves a consistent ring:
+//! at worst the oldest entries are gone while the new one is not there yet.
+
+use ::std::io;
+use ::std::path::Path;
+use ::std::ptr;
+
+use ::pmem::{self, crc::crc32, pmap::PersistentMap};
+
+const MAGIC: &[u8; 4] = b"PRNG";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 64;
+const CAPACITY_OFFSET: usize = 8;
+const HEAD_OFFSET: usize = 16;
+const TAIL_OFFSET: usize = 24;
+
+/// Size in bytes of the frame header
+pub const FRAME_HEADER_SIZE: usize = 8;
+
+fn persist(map: &PersistentMap, start: usize, len: usize) -> Result<(), io::Error> {
+    pmem::msync_unsized(&map[start..start + len]).map_err(io::Error::from)
+}
+
+fn read_u64(map: &PersistentMap, at: usize) -> u64 {
+    // the header fields are 8 byte aligned within the page aligned mapping
+    u64::from_le(unsafe { ptr::read_volatile(map[at..].as_ptr() as *const u64) })
+}
+
+/// Stores `value` with a single aligned 8 byte write and persists it
+fn write_u64(map: &mut PersistentMap, at: usize, value: u64) -> Result<(), io::Error> {
+    unsafe { ptr::write_volatile(map[at..].as_mut_ptr() as *mut u64, value.to_le()) };
+    persist(map, at, 8)
+}
+
+/// Circular log overwriting its oldest entries
+pub struct RingLog {
+    map: PersistentMap,
+    capacity: usize,
+}
+
+impl RingLog {
+    /// Creates a ring in a file of `size` bytes at `path`
+    pub fn create<P: AsRef<Path>>(path: P, size: usize) -> Result<Self, io::Error> {
+        RingLog::create_mode(path, size, 0o666)
+    }
+
+    /// Like `create()`, with the permissions `mode` for the file, the process umask applies
+    pub fn create_mode<P: AsRef<Path>>(path: P, size: usize, mode: u32) -> Result<Self, io::Error> {
+        if size <= HEADER_SIZE + FRAME_HEADER_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("A ring needs more than {} bytes",
+                                              HEADER_SIZE + FRAME_HEADER_SIZE)));
+        }
+        let mut map = PersistentMap::create(path, size, false, mode)?;
+        let capacity = map.len() - HEADER_SIZE;
+        for b in map[..HEADER_SIZE].iter_mut() {
+            *b = 0;
+        }
+        map[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        map[CAPACITY_OFFSET..CAPACITY_OFFSET + 8].copy_from_slice(&(capacity as u64).to_le_bytes());
+        persist(&map, 0, HEADER_SIZE)?;
+        // the magic goes last, a crash before it leaves an invalid file
+        map[..4].copy_from_slice(MAGIC);
+        persist(&map, 0, 4)?;
+        Ok(RingLog { map, capacity })
+    }
+
+    /// Opens the ring at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        let map = PersistentMap::open(path)?;
+        if map.len() < HEADER_SIZE || &map[..4] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a ring log"));
+        }
+        let mut version = [0; 4];
+        version.copy_from_slice(&map[4..8]);
+        let version = u32::from_le_bytes(version);
+        if version != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Unsupported ring log version {}", version)));
+        }
+        let capacity = read_u64(&map, CAPACITY_OFFSET) as usize;
+        let (head, tail) = (read_u64(&map, HEAD_OFFSET), read_u64(&map, TAIL_OFFSET));
+        if capacity <= FRAME_HEADER_SIZE || capacity > map.len() - HEADER_SIZE || head > tail ||
+           tail - head > capacity as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Corrupt ring log header"));
+        }
+        Ok(RingLog { map, capacity })
+    }
+
+    /// The size of the data area in bytes, frame headers included
+    pub fn capacity(&self) -> usize { self.capacity }
+
+    /// The largest entry the ring can hold
+    pub fn max_entry_size(&self) -> usize { (self.capacity - FRAME_HEADER_SIZE).min(u32::MAX as usize) }
+
+    fn head(&self) -> u64 { read_u64(&self.map, HEAD_OFFSET) }
+
+    fn tail(&self) -> u64 { read_u64(&self.map, TAIL_OFFSET) }
+
+    /// The number of bytes in use, frame headers included
+    pub fn len(&self) -> usize { (self.tail() - self.head()) as usize }
+
+    /// Whether the ring holds no entries
+    pub fn is_empty(&self) -> bool { self.head() == self.tail() }
+
+    /// Copies the data at logical offset `at` into `buf`, wrapping around the end of the data area
+    fn read_at(&self, at: u64, buf: &mut [u8]) {
+        let pos = (at % self.capacity as u64) as usize;
+        let first = buf.len().min(self.capacity - pos);
+        buf[..first].copy_from_slice(&self.map[HEADER_SIZE + pos..HEADER_SIZE + pos + first]);
+        let rest = buf.len() - first;
+        buf[first..].copy_from_slice(&self.map[HEADER_SIZE..HEADER_SIZE + rest]);
+    }
+
+    /// Writes `buf` at logical offset `at` and persists it, wrapping around the end of the data area
+    fn write_at(&mut self, at: u64, buf: &[u8]) -> Result<(), io::Error> {
+        let pos = (at % self.capacity as u64) as usize;
+        let first = buf.len().min(self.capacity - pos);
+        self.map[HEADER_SIZE + pos..HEADER_SIZE + pos + first].copy_from_slice(&buf[..first]);
+        persist(&self.map, HEADER_SIZE + pos, first)?;
+        let rest = buf.len() - first;
+        if rest > 0 {
+            self.map[HEADER_SIZE..HEADER_SIZE + rest].copy_from_slice(&buf[first..]);
+            persist(&self.map, HEADER_SIZE, rest)?;
+        }
+        Ok(())
+    }
+
+    /// The length of the entry in the frame at logical offset `at`
+    fn entry_len(&self, at: u64) -> usize {
+        let mut len = [0; 4];
+        self.read_at(at, &mut len);
+        u32::from_le_bytes(len) as usize
+    }
+
+    /// Appends an entry, overwriting the oldest entries if there is not enough room
+    pub fn append<T: AsRef<[u8]>>(&mut self, entry: T) -> Result<(), io::Error> {
+        let entry = entry.as_ref();
+        if entry.len() > self.max_entry_size() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Entry of {} bytes is larger than the maximum of {}",
+                                              entry.len(),
+                                              self.max_entry_size())));
+        }
+        let frame_len = (FRAME_HEADER_SIZE + entry.len()) as u64;
+        let tail = self.tail();
+
+        let mut head = self.head();
+        while tail + frame_len - head > self.capacity as u64 {
+            // never past the tail, even if a length got corrupted
+            head = (head + (FRAME_HEADER_SIZE + self.entry_len(head)) as u64).min(tail);
+        }
+        if head != self.head() {
+            write_u64(&mut self.map, HEAD_OFFSET, head)?;
+        }
+
+        let mut header = [0; FRAME_HEADER_SIZE];
+        header[..4].copy_from_slice(&(entry.len() as u32).to_le_bytes());
+        header[4..].copy_from_slice(&crc32(entry).to_le_bytes());
+        self.write_at(tail, &header)?;
+        self.write_at(tail + FRAME_HEADER_SIZE as u64, entry)?;
+
+        write_u64(&mut self.map, TAIL_OFFSET, tail + frame_len)
+    }
+
+    /// Discards all the entries
+    pub fn clear(&mut self) -> Result<(), io::Error> {
+        let tail = self.tail();
+        write_u64(&mut self.map, HEAD_OFFSET, tail)
+    }
+
+    /// Iterates over the entries, oldest first
+    pub fn iter(&self) -> RingEntries<'_> { RingEntries { ring: self, pos: self.head(), end: self.tail() } }
+}
+
+/// Iterator over the entries of a `RingLog`, created by `RingLog::iter()`
+///
+/// If an entry does not match its checksum, the iterator yields an `ErrorKind::InvalidData` error and stops.
+pub struct RingEntries<'a> {
+    ring: &'a RingLog,
+    pos: u64,
+    end: u64,
+}
+
+impl<'a> Iterator for RingEntries<'a> {
+    type Item = Result<Vec<u8>, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.end - self.pos < FRAME_HEADER_SIZE as u64 {
+            return None;
+        }
+        let mut header = [0; FRAME_HEADER_SIZE];
+        self.ring.read_at(self.pos, &mut header);
+        let mut len = [0; 4];
+        len.copy_from_slice(&header[..4]);
+        let len = u32::from_le_bytes(len) as usize;
+        let mut crc = [0; 4];
+        crc.copy_from_slice(&header[4..]);
+        let crc = u32::from_le_bytes(crc);
+
+        let start = self.pos;
+        let mut entry = vec![0; len.min((self.end - start) as usize)];
+        self.ring.read_at(start + FRAME_HEADER_SIZE as u64, &mut entry);
+        if (self.end - start - FRAME_HEADER_SIZE as u64) < len as u64 || crc32(&entry) != crc {
+            self.pos = self.end;
+            return Some(Err(io::Error::new(io::ErrorKind::InvalidData,
+                                           format!("Corrupt entry at offset {}", start))));
+        }
+        self.pos += (FRAME_HEADER_SIZE + len) as u64;
+        Some(Ok(entry))
+    }
+}
@@ -0,0 +1,190 @@
+//! Logs spanning multiple pool files
+//!
+//! A single `Log` has a fixed capacity chosen at `Log::create`.
+//! A `SegmentedLog` manages a directory of pmemlog pools, the segments,
+//! and rolls over to a new segment whenever the current one is full.
+//!
+//! Every segment file is named after the global offset of its first byte, for example `00000000000000004096.pmemlog`,
+//! so offsets keep growing across segments and old segments can be deleted once they are no longer needed.
+
+use ::std::fs;
+use ::std::io;
+use ::std::ops::ControlFlow;
+use ::std::path::{Path, PathBuf};
+
+use log::Log;
+
+const SEGMENT_EXTENSION: &str = "pmemlog";
+
+fn segment_path(dir: &Path, base: u64) -> PathBuf { dir.join(format!("{:020}.{}", base, SEGMENT_EXTENSION)) }
+
+fn sync_dir(dir: &Path) -> Result<(), io::Error> { fs::File::open(dir)?.sync_all() }
+
+/// Log made of a directory of segments
+pub struct SegmentedLog {
+    dir: PathBuf,
+    segment_size: usize,
+    /// Base offsets of all the segments, oldest first, the last one is `active`
+    bases: Vec<u64>,
+    active: Log,
+}
+
+impl SegmentedLog {
+    /// Creates a new segmented log in `dir`, each segment being a pool of `segment_size` bytes
+    ///
+    /// The directory is created if needed, it must not contain any segment yet.
+    pub fn create<P: AsRef<Path>>(dir: P, segment_size: usize) -> Result<Self, io::Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        if !SegmentedLog::scan(&dir)?.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                      format!("{} already contains a segmented log", dir.display())));
+        }
+        let active = Log::create(segment_path(&dir, 0), segment_size)?;
+        sync_dir(&dir)?;
+        Ok(SegmentedLog { dir, segment_size, bases: vec![0], active })
+    }
+
+    /// Opens the segmented log in `dir`
+    ///
+    /// New segments get the size of the most recent one.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, io::Error> {
+        let dir = dir.as_ref().to_path_buf();
+        let bases = SegmentedLog::scan(&dir)?;
+        let last = match bases.last() {
+            Some(&base) => segment_path(&dir, base),
+            None => {
+                return Err(io::Error::new(io::ErrorKind::NotFound,
+                                          format!("{} does not contain a segmented log", dir.display())))
+            }
+        };
+        let segment_size = fs::metadata(&last)?.len() as usize;
+        let active = Log::open(&last)?;
+        Ok(SegmentedLog { dir, segment_size, bases, active })
+    }
+
+    /// Base offsets of the segments found in `dir`, sorted
+    fn scan(dir: &Path) -> Result<Vec<u64>, io::Error> {
+        let mut bases = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            if let Some(base) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok()) {
+                bases.push(base);
+            }
+        }
+        bases.sort();
+        Ok(bases)
+    }
+
+    /// The directory holding the segments
+    pub fn dir(&self) -> &Path { &self.dir }
+
+    /// The size in bytes of the pool backing each new segment
+    pub fn segment_size(&self) -> usize { self.segment_size }
+
+    /// The number of segments
+    pub fn segment_count(&self) -> usize { self.bases.len() }
+
+    /// The global offsets the segments start at, oldest first
+    pub fn segment_bases(&self) -> &[u64] { &self.bases }
+
+    /// The global offset of the oldest byte still in the log
+    pub fn start(&self) -> u64 { self.bases[0] }
+
+    /// The global offset the next append will be written at
+    pub fn len(&self) -> u64 { self.active_base() + self.active.len() as u64 }
+
+    /// Whether nothing is left in the log
+    pub fn is_empty(&self) -> bool { self.len() == self.start() }
+
+    fn active_base(&self) -> u64 { *self.bases.last().unwrap() }
+
+    /// Makes sure the active segment has room for `len` more bytes, rolling over to a new segment if needed
+    fn reserve(&mut self, len: usize) -> Result<(), io::Error> {
+        if self.active.capacity() - self.active.len() >= len {
+            return Ok(());
+        }
+        // all the segments have the same capacity, check before creating one that would be left behind
+        if self.active.is_empty() || self.active.capacity() < len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("An append of {} bytes does not fit in a segment of {} bytes",
+                                              len,
+                                              self.active.capacity())));
+        }
+
+        let base = self.len();
+        let log = Log::create(segment_path(&self.dir, base), self.segment_size)?;
+        sync_dir(&self.dir)?;
+        self.active = log;
+        self.bases.push(base);
+        Ok(())
+    }
+
+    /// Appends an entry, returning the global offset it was written at
+    ///
+    /// An entry never spans two segments, it must fit in an empty segment.
+    pub fn append<T: AsRef<[u8]>>(&mut self, entry: T) -> Result<u64, io::Error> {
+        let entry = entry.as_ref();
+        self.reserve(entry.len())?;
+        let offset = self.len();
+        self.active.append(entry)?;
+        Ok(offset)
+    }
+
+    /// Appends several entries with a single atomic append, returning the global offset of the first one
+    ///
+    /// All the entries are written to the same segment.
+    pub fn append_many<T: AsRef<[u8]>>(&mut self, entries: &[T]) -> Result<u64, io::Error> {
+        let len = entries.iter().map(|e| e.as_ref().len()).sum();
+        self.reserve(len)?;
+        let offset = self.len();
+        self.active.append_many(entries)?;
+        Ok(offset)
+    }
+
+    /// Walks through the log from the oldest segment, calling `callback` with the global offset of each chunk
+    /// and chunks of at most `chunk_size` bytes
+    ///
+    /// Chunks never span two segments. See `Log::walk()`.
+    pub fn walk<F, E>(&self, chunk_size: usize, mut callback: F) -> Result<(), E>
+        where F: FnMut(u64, &[u8]) -> ControlFlow<E>,
+              E: From<io::Error>
+    {
+        for (i, &base) in self.bases.iter().enumerate() {
+            let older;
+            let log = if i + 1 == self.bases.len() {
+                &self.active
+            } else {
+                older = Log::open(segment_path(&self.dir, base)).map_err(io::Error::from)?;
+                &older
+            };
+            let mut offset = base;
+            log.walk(chunk_size, |chunk| {
+                let r = callback(offset, chunk);
+                offset += chunk.len() as u64;
+                r
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the segments that only hold data before the global offset `retain`
+    ///
+    /// The active segment is never deleted. Returns the number of segments deleted.
+    pub fn delete_before(&mut self, retain: u64) -> Result<usize, io::Error> {
+        let mut deleted = 0;
+        // the end of a segment is the base of the next one
+        while self.bases.len() > 1 && self.bases[1] <= retain {
+            fs::remove_file(segment_path(&self.dir, self.bases[0]))?;
+            self.bases.remove(0);
+            deleted += 1;
+        }
+        if deleted > 0 {
+            sync_dir(&self.dir)?;
+        }
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,285 @@
+//! Log shared between threads, with group commit
+//!
+//! Every `Log::append` is a separate `pmemlog_append` call, which makes many small concurrent appends slow.
+//! A `SharedLog` batches the appends issued concurrently into a single `pmemlog_appendv` call:
+//! the first thread to arrive becomes the leader and writes everything queued so far,
+//! the threads arriving meanwhile wait for the next batch, led by one of them.
+//!
+//! Readers in the same process can follow the log as it grows with `SharedLog::subscribe()`.
+
+use ::std::collections::HashMap;
+use ::std::io;
+use ::std::mem;
+use ::std::ops::ControlFlow;
+use ::std::sync::{Arc, Condvar, Mutex, Weak};
+use ::std::sync::mpsc::{self, Receiver};
+use ::std::thread;
+use ::std::time::{Duration, Instant};
+
+use log::Log;
+
+struct Queue {
+    /// Entries waiting for the next batch, with their ticket
+    pending: Vec<(u64, Vec<u8>)>,
+    next_ticket: u64,
+    /// Whether a leader is currently writing a batch
+    leader: bool,
+    /// Outcome of the committed entries, by ticket, until their appender picks it up
+    done: HashMap<u64, Result<u64, io::Error>>,
+}
+
+/// Length of the log as seen by the subscribers
+struct Tail {
+    /// The length of the log and whether every `SharedLog` handle is gone
+    state: Mutex<(u64, bool)>,
+    appended: Condvar,
+}
+
+/// The appenders' side of `Tail`, closes it for the subscribers once dropped
+struct Publisher(Arc<Tail>);
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().1 = true;
+        self.0.appended.notify_all();
+    }
+}
+
+struct Inner {
+    log: Mutex<Log>,
+    queue: Mutex<Queue>,
+    committed: Condvar,
+    tail: Publisher,
+}
+
+/// The batch being written by a leader, handed to its appenders once dropped
+///
+/// An entry left without an outcome, when the leader panics, fails with an error,
+/// and the next batch gets a leader.
+struct Leader<'a> {
+    inner: &'a Inner,
+    tickets: Vec<u64>,
+    results: Vec<Result<u64, io::Error>>,
+}
+
+impl<'a> Drop for Leader<'a> {
+    fn drop(&mut self) {
+        let mut queue = self.inner.queue.lock().unwrap_or_else(|e| e.into_inner());
+        queue.leader = false;
+        let mut results = mem::take(&mut self.results).into_iter();
+        for &ticket in &self.tickets {
+            let result = results.next()
+                                .unwrap_or_else(|| Err(io::Error::other("The leader of the batch panicked")));
+            queue.done.insert(ticket, result);
+        }
+        self.inner.committed.notify_all();
+    }
+}
+
+/// Handle to a `Log` shared between threads
+///
+/// Cloning the handle is cheap, all the clones append to the same log.
+#[derive(Clone)]
+pub struct SharedLog {
+    inner: Arc<Inner>,
+}
+
+impl SharedLog {
+    /// Shares `log` between threads
+    pub fn new(log: Log) -> Self {
+        let queue = Queue { pending: Vec::new(), next_ticket: 0, leader: false, done: HashMap::new() };
+        let tail = Tail { state: Mutex::new((log.len() as u64, false)), appended: Condvar::new() };
+        let inner = Inner {
+            log: Mutex::new(log),
+            queue: Mutex::new(queue),
+            committed: Condvar::new(),
+            tail: Publisher(Arc::new(tail)),
+        };
+        SharedLog { inner: Arc::new(inner) }
+    }
+
+    /// Appends an entry, returning the offset it was written at once it is durable
+    ///
+    /// Entries appended concurrently from several threads are written together with a single atomic append.
+    pub fn append<T: AsRef<[u8]>>(&self, entry: T) -> Result<u64, io::Error> {
+        let inner = &*self.inner;
+        let mut queue = inner.queue.lock().unwrap();
+        let ticket = queue.next_ticket;
+        queue.next_ticket += 1;
+        queue.pending.push((ticket, entry.as_ref().to_vec()));
+
+        loop {
+            if let Some(result) = queue.done.remove(&ticket) {
+                return result;
+            }
+            if queue.leader {
+                queue = inner.committed.wait(queue).unwrap();
+                continue;
+            }
+
+            // Lead the next batch, which includes our entry
+            queue.leader = true;
+            let batch = mem::take(&mut queue.pending);
+            drop(queue);
+
+            let mut leader = Leader { inner, tickets: batch.iter().map(|e| e.0).collect(), results: Vec::new() };
+            leader.results = self.commit(&batch);
+            drop(leader);
+
+            queue = inner.queue.lock().unwrap();
+        }
+    }
+
+    /// Writes a batch, returning the outcome of each entry
+    fn commit(&self, batch: &[(u64, Vec<u8>)]) -> Vec<Result<u64, io::Error>> {
+        let mut log = self.inner.log.lock().unwrap();
+        let results = SharedLog::append_batch(&mut log, batch);
+
+        let tail = &self.inner.tail.0;
+        tail.state.lock().unwrap().0 = log.len() as u64;
+        tail.appended.notify_all();
+        results
+    }
+
+    fn append_batch(log: &mut Log, batch: &[(u64, Vec<u8>)]) -> Vec<Result<u64, io::Error>> {
+        let start = log.len() as u64;
+        let entries: Vec<&[u8]> = batch.iter().map(|e| &e.1[..]).collect();
+
+        match log.append_many(&entries) {
+            Ok(()) => {
+                let mut offset = start;
+                entries.iter()
+                       .map(|e| {
+                           let r = Ok(offset);
+                           offset += e.len() as u64;
+                           r
+                       })
+                       .collect()
+            }
+            Err(err) => {
+                if entries.len() == 1 {
+                    return vec![Err(err.into())];
+                }
+                // The batch as a whole may not fit, give each entry a chance on its own
+                entries.iter()
+                       .map(|e| {
+                           let offset = log.len() as u64;
+                           log.append(e).map(|_| offset).map_err(io::Error::from)
+                       })
+                       .collect()
+            }
+        }
+    }
+
+    /// The number of bytes in the log
+    pub fn len(&self) -> usize { self.inner.log.lock().unwrap().len() }
+
+    /// Whether the log is empty
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// The capacity of the log in bytes
+    pub fn capacity(&self) -> usize { self.inner.log.lock().unwrap().capacity() }
+
+    /// Walks through the log, see `Log::walk()`
+    ///
+    /// Appends wait until the walk is over.
+    pub fn walk<F, E>(&self, chunk_size: usize, callback: F) -> Result<(), E>
+        where F: FnMut(&[u8]) -> ControlFlow<E>
+    {
+        self.inner.log.lock().unwrap().walk(chunk_size, callback)
+    }
+
+    /// Follows the log from offset `from`, yielding the data appended after it
+    ///
+    /// Data already in the log past `from` is yielded right away.
+    /// The iterator blocks waiting for new appends and ends once every `SharedLog` handle has been dropped.
+    pub fn subscribe(&self, from: u64) -> Subscription {
+        Subscription { inner: Arc::downgrade(&self.inner), tail: self.inner.tail.0.clone(), pos: from }
+    }
+
+    /// Like `subscribe()`, but delivers the data through a channel fed by a background thread
+    ///
+    /// The thread stops once the log is dropped or the receiver is gone.
+    pub fn subscribe_channel(&self, from: u64) -> Receiver<(u64, Vec<u8>)> {
+        let (tx, rx) = mpsc::channel();
+        let subscription = self.subscribe(from);
+        thread::spawn(move || {
+            for data in subscription {
+                if tx.send(data).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+
+    /// Unwraps the log if this is the last handle to it
+    ///
+    /// The subscriptions end, like when the last handle is dropped.
+    pub fn try_unwrap(self) -> Result<Log, SharedLog> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(inner) => Ok(inner.log.into_inner().unwrap()),
+            Err(inner) => Err(SharedLog { inner }),
+        }
+    }
+}
+
+/// Blocking iterator over the data appended to a `SharedLog`, created by `SharedLog::subscribe()`
+///
+/// Each item is the offset of a chunk and the bytes appended from there up to the end of the log at that time.
+/// Chunks do not follow the boundaries of the appended entries.
+pub struct Subscription {
+    inner: Weak<Inner>,
+    tail: Arc<Tail>,
+    pos: u64,
+}
+
+impl Subscription {
+    /// The offset of the next byte this subscription will yield
+    pub fn offset(&self) -> u64 { self.pos }
+
+    /// Like `next()`, but gives up after `timeout`
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<(u64, Vec<u8>)> {
+        self.wait(Some(Instant::now() + timeout))
+    }
+
+    /// Like `next()`, but returns `None` instead of blocking when there is no new data
+    pub fn try_next(&mut self) -> Option<(u64, Vec<u8>)> { self.wait(Some(Instant::now())) }
+
+    fn wait(&mut self, deadline: Option<Instant>) -> Option<(u64, Vec<u8>)> {
+        {
+            let mut state = self.tail.state.lock().unwrap();
+            while state.0 <= self.pos {
+                if state.1 {
+                    return None;
+                }
+                state = match deadline {
+                    None => self.tail.appended.wait(state).unwrap(),
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return None;
+                        }
+                        self.tail.appended.wait_timeout(state, deadline - now).unwrap().0
+                    }
+                };
+            }
+        }
+
+        let inner = self.inner.upgrade()?;
+        let log = inner.log.lock().unwrap();
+        let data = log.contents();
+        if data.len() as u64 <= self.pos {
+            return None;
+        }
+        let offset = self.pos;
+        self.pos = data.len() as u64;
+        Some((offset, data[offset as usize..].to_vec()))
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = (u64, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> { self.wait(None) }
+}
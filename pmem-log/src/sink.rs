@@ -0,0 +1,138 @@
+//! Application logs surviving crashes
+//!
+//! A `Sink` formats log records as text lines and appends each one as a frame of a `RecordLog`,
+//! or of a `SegmentedLog` that keeps rotating segments.
+//! The appends are durable as soon as they return, without any `fsync(2)`,
+//! so the last events before a crash are always there to read back.
+//!
+//! With the `log` feature a `Sink` can be installed as the logger of the `log` facade,
+//! with the `tracing` feature it is a `tracing_subscriber::Layer`.
+//! The `pmem-log-read` binary prints the records.
+//!
+//! Each line looks like `1500000000.123456 INFO  my_app::module: message key=value`.
+
+use ::std::fmt::{self, Write};
+use ::std::io;
+use ::std::sync::Mutex;
+use ::std::time::{SystemTime, UNIX_EPOCH};
+
+use record::{self, RecordLog};
+use segmented::SegmentedLog;
+
+enum Target {
+    Single(RecordLog),
+    Rotating { log: SegmentedLog, max_segments: usize },
+}
+
+/// Appends formatted log records to a log
+pub struct Sink {
+    target: Mutex<Target>,
+}
+
+impl Sink {
+    /// Appends to `log`
+    ///
+    /// Once the log is full the records are dropped.
+    pub fn new(log: RecordLog) -> Self { Sink { target: Mutex::new(Target::Single(log)) } }
+
+    /// Appends to `log`, deleting the oldest segments to keep at most `max_segments` of them
+    pub fn rotating(log: SegmentedLog, max_segments: usize) -> Self {
+        Sink { target: Mutex::new(Target::Rotating { log, max_segments: max_segments.max(1) }) }
+    }
+
+    /// Appends `line` as a single record
+    pub fn append(&self, line: &str) -> Result<(), io::Error> {
+        match *self.target.lock().unwrap() {
+            Target::Single(ref mut log) => log.append(line),
+            Target::Rotating { ref mut log, max_segments } => {
+                let header = record::header(line.len())?;
+                log.append_many(&[&header[..], line.as_bytes()])?;
+                if log.segment_count() > max_segments {
+                    let retain = log.segment_bases()[log.segment_count() - max_segments];
+                    log.delete_before(retain)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Formats and appends a record
+    ///
+    /// Errors are dropped, a logger has nowhere to report them.
+    pub fn record(&self, level: &str, target: &str, message: fmt::Arguments<'_>) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut line = String::new();
+        let _ = write!(line,
+                       "{}.{:06} {:<5} {}: {}",
+                       now.as_secs(),
+                       now.subsec_micros(),
+                       level,
+                       target,
+                       message);
+        let _ = self.append(&line);
+    }
+}
+
+#[cfg(feature = "log")]
+mod log_facade {
+    use ::log_crate::{self, Metadata, Record};
+
+    use super::Sink;
+
+    impl log_crate::Log for Sink {
+        fn enabled(&self, _: &Metadata<'_>) -> bool { true }
+
+        fn log(&self, record: &Record<'_>) {
+            self.record(record.level().as_str(), record.target(), *record.args());
+        }
+
+        /// The records are durable once appended, nothing to flush
+        fn flush(&self) {}
+    }
+}
+
+#[cfg(feature = "tracing")]
+mod tracing_layer {
+    use ::std::fmt::{self, Write};
+
+    use ::tracing_core::field::{Field, Visit};
+    use ::tracing_core::{Event, Subscriber};
+    use ::tracing_subscriber::layer::{Context, Layer};
+
+    use super::Sink;
+
+    /// Formats the fields of an event, the message first
+    struct Fields {
+        message: String,
+        rest: String,
+    }
+
+    impl Visit for Fields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "message" {
+                self.message.push_str(value);
+            } else {
+                let _ = write!(self.rest, " {}={:?}", field.name(), value);
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                let _ = write!(self.message, "{:?}", value);
+            } else {
+                let _ = write!(self.rest, " {}={:?}", field.name(), value);
+            }
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Sink {
+        fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+            let mut fields = Fields { message: String::new(), rest: String::new() };
+            event.record(&mut fields);
+            let metadata = event.metadata();
+            self.record(metadata.level().as_str(),
+                        metadata.target(),
+                        format_args!("{}{}", fields.message, fields.rest));
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! Write-ahead logging
+//!
+//! A `Wal` numbers every record it appends to a `Log` with a log sequence number (`Lsn`),
+//! tags it with a type and protects it with a CRC-32.
+//! `replay()` reads the records back from a given `Lsn` and stops at the first corrupt one,
+//! `checkpoint()` lets the records before an `Lsn` be reclaimed.
+//!
+//! A corrupt frame at the very end of the log is a torn tail, discarded by the next append.
+//! One followed by valid frames is damage, appends are refused until `repair()` is called.
+//!
+//! Each frame is laid out as follows, all the integers being little-endian:
+//!
+//! ```text
+//! | len: u32 | crc: u32 | lsn: u64 | kind: u8 | data: [u8; len] |
+//! ```
+//!
+//! The CRC covers every other field of the frame.
+//! Kind 0 is reserved for the checkpoint frames, which hold the `Lsn` of the checkpoint and keep
+//! the sequence going once all the older records are gone.
+
+use ::std::fmt;
+use ::std::io;
+use ::std::path::Path;
+
+use ::pmem::crc::crc32_parts;
+
+use log::Log;
+
+/// Size in bytes of the frame header
+pub const HEADER_SIZE: usize = 17;
+
+/// Largest record a frame can hold
+pub const MAX_RECORD_SIZE: usize = u32::MAX as usize;
+
+/// Kind of the internal checkpoint frames
+const CHECKPOINT: u8 = 0;
+
+/// Log sequence number
+///
+/// Every record appended to a `Wal` gets the next number, starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Lsn(pub u64);
+
+impl fmt::Display for Lsn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { self.0.fmt(f) }
+}
+
+/// Record read back from a `Wal`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalRecord<'a> {
+    pub lsn: Lsn,
+    /// The type tag given to `append()`
+    pub kind: u8,
+    pub data: &'a [u8],
+}
+
+/// A frame parsed from the log
+struct Frame<'a> {
+    lsn: Lsn,
+    kind: u8,
+    data: &'a [u8],
+    len: usize,
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&buf[at..at + 4]);
+    u32::from_le_bytes(b)
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&buf[at..at + 8]);
+    u64::from_le_bytes(b)
+}
+
+/// Parses the frame at the start of `buf`, `None` if it is torn or corrupt
+fn parse(buf: &[u8]) -> Option<Frame<'_>> {
+    if buf.len() < HEADER_SIZE {
+        return None;
+    }
+    let len = u32_at(buf, 0) as usize;
+    if buf.len() - HEADER_SIZE < len {
+        return None;
+    }
+    let crc = u32_at(buf, 4);
+    if crc32_parts(&[&buf[..4], &buf[8..HEADER_SIZE + len]]) != crc {
+        return None;
+    }
+    Some(Frame {
+        lsn: Lsn(u64_at(buf, 8)),
+        kind: buf[16],
+        data: &buf[HEADER_SIZE..HEADER_SIZE + len],
+        len: HEADER_SIZE + len,
+    })
+}
+
+/// Whether a valid frame continuing the sequence at `next` starts anywhere in `data` but at its start
+fn has_valid_frame(data: &[u8], next: Lsn) -> bool {
+    (1..data.len()).any(|at| parse(&data[at..]).is_some_and(|frame| frame.lsn >= next))
+}
+
+/// Write-ahead log over a `Log`
+pub struct Wal {
+    log: Log,
+    next: Lsn,
+    checkpoint: Lsn,
+    /// Length of the valid frames, anything past it is a corrupt tail or damaged
+    end: usize,
+    /// Whether the corrupt data past `end` is not a torn tail, see `is_damaged()`
+    damaged: bool,
+}
+
+impl Wal {
+    /// Wraps an existing log, scanning it to find where the sequence stands
+    ///
+    /// The log must only contain frames written by a `Wal`, anything else makes it damaged.
+    pub fn new(log: Log) -> Self {
+        let mut next = Lsn(1);
+        let mut checkpoint = Lsn(0);
+        let mut end = 0;
+        {
+            let data = log.contents();
+            while let Some(frame) = parse(&data[end..]) {
+                if frame.lsn < next {
+                    break;
+                }
+                if frame.kind == CHECKPOINT && frame.data.len() == 8 {
+                    checkpoint = Lsn(u64_at(frame.data, 0));
+                }
+                next = Lsn(frame.lsn.0 + 1);
+                end += frame.len;
+            }
+        }
+        // A torn tail is the end of the log, and comes after at least one valid frame.
+        // Otherwise the log is damaged or not a `Wal` at all, nothing is discarded without `repair()`
+        let damaged = {
+            let data = log.contents();
+            end < data.len() && (end == 0 || has_valid_frame(&data[end..], next))
+        };
+        Wal { log, next, checkpoint, end, damaged }
+    }
+
+    /// Opens the log at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> { Ok(Wal::new(Log::open(path)?)) }
+
+    /// Creates a log of `size` bytes at `path`
+    pub fn create<P: AsRef<Path>>(path: P, size: usize) -> Result<Self, io::Error> {
+        Ok(Wal::new(Log::create(path, size)?))
+    }
+
+    /// The underlying log
+    pub fn get_ref(&self) -> &Log { &self.log }
+
+    /// Unwraps this `Wal`, returning the underlying log
+    pub fn into_inner(self) -> Log { self.log }
+
+    /// The `Lsn` the next record will get
+    pub fn next_lsn(&self) -> Lsn { self.next }
+
+    /// The `Lsn` of the last checkpoint, `Lsn(0)` if there was none
+    pub fn checkpoint_lsn(&self) -> Lsn { self.checkpoint }
+
+    /// Whether the log ends with a corrupt or torn frame
+    ///
+    /// The corrupt tail is discarded by the next append.
+    pub fn has_corrupt_tail(&self) -> bool { !self.damaged && self.end < self.log.len() }
+
+    /// Whether a corrupt frame is followed by valid ones, or the log does not start with a valid frame
+    ///
+    /// Appending to a damaged log fails with `ErrorKind::InvalidData`, `replay()` stops at the corrupt frame.
+    pub fn is_damaged(&self) -> bool { self.damaged }
+
+    /// Discards everything from the first corrupt frame on, so a damaged log can be appended to again
+    ///
+    /// Returns the number of bytes discarded. The records after the corrupt frame are lost,
+    /// they can still be salvaged from the log before calling this.
+    pub fn repair(&mut self) -> Result<usize, io::Error> {
+        let discarded = self.log.len() - self.end;
+        if discarded > 0 {
+            self.log.truncate(self.end)?;
+        }
+        self.damaged = false;
+        Ok(discarded)
+    }
+
+    fn append_frame(&mut self, kind: u8, data: &[u8]) -> Result<Lsn, io::Error> {
+        if data.len() > MAX_RECORD_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Record of {} bytes is larger than the maximum of {}",
+                                              data.len(),
+                                              MAX_RECORD_SIZE)));
+        }
+        if self.damaged {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Corrupt frame at offset {} followed by valid ones, see repair()",
+                                              self.end)));
+        }
+        if self.has_corrupt_tail() {
+            self.log.truncate(self.end)?;
+        }
+
+        let lsn = self.next;
+        let mut header = [0; HEADER_SIZE];
+        header[..4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        header[8..16].copy_from_slice(&lsn.0.to_le_bytes());
+        header[16] = kind;
+        let crc = crc32_parts(&[&header[..4], &header[8..], data]);
+        header[4..8].copy_from_slice(&crc.to_le_bytes());
+
+        self.log.append_many(&[&header[..], data])?;
+        self.end += HEADER_SIZE + data.len();
+        self.next = Lsn(lsn.0 + 1);
+        Ok(lsn)
+    }
+
+    /// Appends a record of type `kind`, returning its `Lsn`
+    ///
+    /// The record is durable once this returns. Kind 0 is reserved.
+    pub fn append<T: AsRef<[u8]>>(&mut self, kind: u8, data: T) -> Result<Lsn, io::Error> {
+        if kind == CHECKPOINT {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Record kind 0 is reserved"));
+        }
+        self.append_frame(kind, data.as_ref())
+    }
+
+    /// Records that everything before `lsn` has been applied, and reclaims the space of those records
+    ///
+    /// A checkpoint frame is appended first, then the log is truncated to start at the first record
+    /// at or after `lsn`, see `Log::truncate_front()`.
+    pub fn checkpoint(&mut self, lsn: Lsn) -> Result<(), io::Error> {
+        if lsn > self.next {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Checkpoint at {} is past the next LSN {}", lsn, self.next)));
+        }
+        self.append_frame(CHECKPOINT, &lsn.0.to_le_bytes())?;
+        self.checkpoint = lsn;
+
+        let mut upto = 0;
+        {
+            let data = self.log.contents();
+            while let Some(frame) = parse(&data[upto..self.end]) {
+                if frame.lsn >= lsn && frame.kind != CHECKPOINT {
+                    break;
+                }
+                if upto + frame.len == self.end {
+                    // keep the checkpoint frame we just wrote
+                    break;
+                }
+                upto += frame.len;
+            }
+        }
+        self.log.truncate_front(upto)?;
+        self.end -= upto;
+        Ok(())
+    }
+
+    /// Reads the records back, starting at `from`
+    ///
+    /// If a corrupt or torn frame is found, the iterator yields an `ErrorKind::InvalidData` error and stops.
+    pub fn replay(&self, from: Lsn) -> Replay<'_> {
+        Replay { data: self.log.contents(), pos: 0, from, last: Lsn(0), done: false }
+    }
+}
+
+/// Iterator over the records of a `Wal`, created by `Wal::replay()`
+pub struct Replay<'a> {
+    data: &'a [u8],
+    pos: usize,
+    from: Lsn,
+    last: Lsn,
+    done: bool,
+}
+
+impl<'a> Iterator for Replay<'a> {
+    type Item = Result<WalRecord<'a>, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done && self.pos < self.data.len() {
+            let frame = match parse(&self.data[self.pos..]) {
+                Some(frame) if frame.lsn > self.last => frame,
+                _ => {
+                    self.done = true;
+                    return Some(Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                   format!("Corrupt frame at offset {} after LSN {}",
+                                                           self.pos,
+                                                           self.last))));
+                }
+            };
+            self.pos += frame.len;
+            self.last = frame.lsn;
+            if frame.kind != CHECKPOINT && frame.lsn >= self.from {
+                return Some(Ok(WalRecord { lsn: frame.lsn, kind: frame.kind, data: frame.data }));
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,95 @@
+//! Buffered writes to a log
+//!
+//! Writing to a `Log` through `io::Write` appends on every call, which is slow for many small writes
+//! and splits a value written in several calls across several appends.
+//! A `LogWriter` keeps the writes in memory and appends them all together on `flush()`,
+//! with a single atomic append.
+
+use ::std::io::{self, Write};
+
+use log::Log;
+
+/// Default amount of buffered data that triggers a flush
+pub const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+/// Buffered writer over a `Log`
+///
+/// Everything written between two flushes is appended atomically: after a crash either all of it is in
+/// the log or none of it is. A flush happens when `flush()` is called, when the buffered data would
+/// grow past the capacity, and when the writer is dropped.
+/// To make a value atomic, write it whole between two calls to `flush()` and keep it smaller than the capacity.
+pub struct LogWriter {
+    /// Only `None` once `into_inner()` took it
+    log: Option<Log>,
+    /// The buffered writes, appended in order on flush
+    pending: Vec<Vec<u8>>,
+    buffered: usize,
+    capacity: usize,
+}
+
+impl LogWriter {
+    /// Buffers the writes to `log`, flushing once `DEFAULT_CAPACITY` bytes are buffered
+    pub fn new(log: Log) -> Self { LogWriter::with_capacity(DEFAULT_CAPACITY, log) }
+
+    /// Buffers the writes to `log`, flushing once `capacity` bytes are buffered
+    pub fn with_capacity(capacity: usize, log: Log) -> Self {
+        LogWriter { log: Some(log), pending: Vec::new(), buffered: 0, capacity }
+    }
+
+    /// The underlying log
+    pub fn get_ref(&self) -> &Log { self.log.as_ref().unwrap() }
+
+    fn log_mut(&mut self) -> &mut Log { self.log.as_mut().unwrap() }
+
+    /// The number of bytes waiting for the next flush
+    pub fn buffered(&self) -> usize { self.buffered }
+
+    /// The amount of buffered data that triggers a flush
+    pub fn capacity(&self) -> usize { self.capacity }
+
+    /// Flushes the buffered writes and returns the underlying log
+    ///
+    /// On failure the writer is returned along with the error, the writes are still buffered.
+    pub fn into_inner(mut self) -> Result<Log, (io::Error, LogWriter)> {
+        match self.flush() {
+            Ok(()) => Ok(self.log.take().unwrap()),
+            Err(err) => Err((err, self)),
+        }
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffered + buf.len() > self.capacity {
+            self.flush()?;
+        }
+        if buf.len() >= self.capacity {
+            return self.log_mut().write(buf);
+        }
+        self.pending.push(buf.to_vec());
+        self.buffered += buf.len();
+        Ok(buf.len())
+    }
+
+    /// Appends all the buffered writes with a single atomic append
+    ///
+    /// If the append fails nothing is written and the writes stay buffered.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.log.as_mut().unwrap().append_many(&self.pending)?;
+        self.pending.clear();
+        self.buffered = 0;
+        Ok(())
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        // errors are dropped, call `flush()` or `into_inner()` to see them
+        if self.log.is_some() {
+            let _ = self.flush();
+        }
+    }
+}
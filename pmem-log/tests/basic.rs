@@ -1,9 +1,14 @@
 extern crate pmem_log;
 
+use ::std::ffi::OsStr;
 use ::std::fs;
+use ::std::io;
+use ::std::ops::ControlFlow;
+use ::std::os::unix::ffi::OsStrExt;
+use ::std::os::unix::fs::PermissionsExt;
 use ::std::path::Path;
 
-use ::pmem_log::Log;
+use ::pmem_log::{Log, LogOptions};
 
 
 #[test]
@@ -36,8 +41,8 @@ fn append_many() {
 
     p.walk(4, |t| {
         println!("Found: {}", String::from_utf8_lossy(t));
-        Some(())
-    });
+        ControlFlow::<()>::Continue(())
+    }).unwrap();
 }
 
 #[test]
@@ -96,8 +101,8 @@ fn walk() {
 
     p.walk(3, |t| {
         println!("Found: {}", String::from_utf8_lossy(t));
-        Some(())
-    });
+        ControlFlow::<()>::Continue(())
+    }).unwrap();
 }
 
 #[test]
@@ -119,6 +124,267 @@ fn walk_after_open() {
 
     p.walk(3, |t| {
         println!("Found: {}", String::from_utf8_lossy(t));
-        Some(())
+        ControlFlow::<()>::Continue(())
+    }).unwrap();
+}
+
+#[test]
+fn rewind() {
+    let path = Path::new("/tmp/test-rewind.pmemlog");
+    if path.exists() {
+        fs::remove_file(&path).unwrap();
+    }
+    let mut p = Log::create(path, 2 * 1024 * 1024).unwrap();
+    p.append("Hello world").unwrap();
+    p.rewind();
+    assert_eq!(p.len(), 0);
+
+    p.append("again").unwrap();
+    assert_eq!(p.len(), 5);
+}
+
+#[test]
+fn truncate_front() {
+    let path = Path::new("/tmp/test-truncate-front.pmemlog");
+    if path.exists() {
+        fs::remove_file(&path).unwrap();
+    }
+
+    {
+        let mut p = Log::create(path, 2 * 1024 * 1024).unwrap();
+        p.append("checkpointed").unwrap();
+        p.append("retained").unwrap();
+        let capacity = p.capacity();
+
+        p.truncate_front(12).unwrap();
+        assert_eq!(p.len(), 8);
+        assert_eq!(p.capacity(), capacity);
+        p.append("-more").unwrap();
+    }
+
+    let mut p = Log::open(path).unwrap();
+    let mut found = Vec::new();
+    p.walk(0, |t| {
+        found.extend_from_slice(t);
+        ControlFlow::<()>::Continue(())
+    }).unwrap();
+    assert_eq!(&found[..], b"retained-more");
+    let len = p.len();
+    assert!(p.truncate_front(len + 1).is_err());
+}
+
+#[test]
+fn truncate_front_keeps_permissions() {
+    let path = Path::new("/tmp/test-truncate-front-permissions.pmemlog");
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut p = Log::create(path, 2 * 1024 * 1024).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600)).unwrap();
+    p.append("checkpointed").unwrap();
+    p.append("retained").unwrap();
+    p.truncate_front(12).unwrap();
+    assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+    assert!(!Path::new("/tmp/test-truncate-front-permissions.pmemlog.tmp").exists());
+
+    // the handle is on the fresh pool
+    p.append("-more").unwrap();
+    drop(p);
+    let p = Log::open(path).unwrap();
+    assert_eq!(p.len(), 13);
+}
+
+#[test]
+fn walk_accumulate() {
+    let path = Path::new("/tmp/test-walk-accumulate.pmemlog");
+    if path.exists() {
+        fs::remove_file(&path).unwrap();
+    }
+
+    let mut p = Log::create(path, 2 * 1024 * 1024).unwrap();
+    p.append("dez").unwrap();
+    p.append("foo").unwrap();
+
+    let mut chunks = Vec::new();
+    p.walk(2, |t| {
+        chunks.push(t.to_vec());
+        ControlFlow::<()>::Continue(())
+    }).unwrap();
+    assert_eq!(chunks, vec![b"de".to_vec(), b"zf".to_vec(), b"oo".to_vec()]);
+}
+
+#[test]
+fn walk_break() {
+    let path = Path::new("/tmp/test-walk-break.pmemlog");
+    if path.exists() {
+        fs::remove_file(&path).unwrap();
+    }
+
+    let mut p = Log::create(path, 2 * 1024 * 1024).unwrap();
+    p.append("dezfoobar").unwrap();
+
+    let mut calls = 0;
+    let r = p.walk(3, |t| {
+        calls += 1;
+        if t == b"foo" {
+            ControlFlow::Break("found")
+        } else {
+            ControlFlow::Continue(())
+        }
     });
+    assert_eq!(r, Err("found"));
+    assert_eq!(calls, 2);
+}
+
+#[test]
+#[should_panic(expected = "boom")]
+fn walk_panic() {
+    let path = Path::new("/tmp/test-walk-panic.pmemlog");
+    if path.exists() {
+        fs::remove_file(&path).unwrap();
+    }
+
+    let mut p = Log::create(path, 2 * 1024 * 1024).unwrap();
+    p.append("dez").unwrap();
+
+    let _ = p.walk(0, |_| -> ControlFlow<()> { panic!("boom") });
+}
+
+#[test]
+fn read_at() {
+    let path = Path::new("/tmp/test-read-at.pmemlog");
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut p = Log::create(path, 2 * 1024 * 1024).unwrap();
+    p.append("dezfoobar").unwrap();
+
+    let mut buf = [0; 4];
+    assert_eq!(p.read_at(3, &mut buf).unwrap(), 4);
+    assert_eq!(&buf, b"foob");
+    assert_eq!(p.read_at(7, &mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"ar");
+    assert_eq!(p.read_at(9, &mut buf).unwrap(), 0);
+    assert_eq!(p.read_at(10, &mut buf).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn walk_from() {
+    let path = Path::new("/tmp/test-walk-from.pmemlog");
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut p = Log::create(path, 2 * 1024 * 1024).unwrap();
+    p.append("dezfoobar").unwrap();
+
+    let mut chunks = Vec::new();
+    p.walk_from(4, 2, |t| {
+        chunks.push(t.to_vec());
+        ControlFlow::<io::Error>::Continue(())
+    }).unwrap();
+    assert_eq!(chunks, vec![b"oo".to_vec(), b"ba".to_vec(), b"r".to_vec()]);
+
+    let r = p.walk_from(10, 0, |_| ControlFlow::<io::Error>::Continue(()));
+    assert_eq!(r.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn truncate() {
+    let path = Path::new("/tmp/test-truncate.pmemlog");
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+    let mut log = Log::create(path, 2 * 1024 * 1024).unwrap();
+    log.append("foobar").unwrap();
+    log.truncate(3).unwrap();
+    assert_eq!(log.len(), 3);
+    assert!(log.truncate(4).is_err());
+}
+
+#[test]
+fn open_error() {
+    let path = Path::new("/tmp/test-open_error-missing/pool.pmemlog");
+    let err = match Log::open(path) {
+        Err(err) => err,
+        Ok(_) => panic!("Opened a missing pool"),
+    };
+    assert_eq!(err.operation(), "pmemlog_open");
+    assert_eq!(err.path(), Some(path));
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    let err: io::Error = err.into();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert!(err.to_string().starts_with("pmemlog_open(/tmp/test-open_error-missing/pool.pmemlog)"));
+}
+
+#[test]
+fn non_utf8_path() {
+    let path = Path::new(OsStr::from_bytes(b"/tmp/test-non_utf8_path-\xff\xfe.pmemlog"));
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+    {
+        let mut p = Log::create(path, 2 * 1024 * 1024).unwrap();
+        p.append(b"hello").unwrap();
+    }
+    assert!(Log::check(path).unwrap());
+    let p = Log::open(path).unwrap();
+    assert_eq!(p.len(), 5);
+    assert_eq!(p.path(), path);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn nul_in_path() {
+    let path = "/tmp/test-nul\0in_path.pmemlog";
+    let err = Log::create(path, 2 * 1024 * 1024).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.operation(), "pmemlog_create");
+    assert_eq!(Log::open(path).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(Log::check(path).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn options() {
+    let path = Path::new("/tmp/test-options.pmemlog");
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut options = Log::options();
+    options.create(true).size(2 * 1024 * 1024).mode(0o600);
+    {
+        let mut p = options.open(path).unwrap();
+        p.append(b"hello").unwrap();
+    }
+    assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+
+    // opens the existing log
+    let p = options.open(path).unwrap();
+    assert_eq!(p.len(), 5);
+    drop(p);
+
+    let err = options.create_new(true).open(path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(err.operation(), "pmemlog_create");
+    fs::remove_file(path).unwrap();
+
+    let err = LogOptions::new().open(path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn options_without_size() {
+    let path = Path::new("/tmp/test-options_without_size.pmemlog");
+    fs::write(path, vec![0; 1024 * 1024]).unwrap();
+    let err = Log::options().create_new(true).open(path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.operation(), "pmemlog_create");
+    assert_eq!(Log::options().create(true).open(path).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    // left as it was
+    assert_eq!(fs::read(path).unwrap(), vec![0; 1024 * 1024]);
+    fs::remove_file(path).unwrap();
 }
@@ -0,0 +1,27 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use ::std::fs;
+use ::std::ops::ControlFlow;
+use ::std::path::Path;
+
+use ::pmem_log::Log;
+
+/// Removes what a previous run left at `path`, a file or a directory
+pub fn clean(path: &Path) {
+    if path.is_dir() {
+        fs::remove_dir_all(path).unwrap();
+    } else if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+/// Everything appended to `log`
+pub fn contents(log: &Log) -> Vec<u8> {
+    let mut data = Vec::new();
+    log.walk(0, |chunk| {
+        data.extend_from_slice(chunk);
+        ControlFlow::<()>::Continue(())
+    }).unwrap();
+    data
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "compression")]
+
+extern crate pmem_log;
+
+mod common;
+
+use ::std::io;
+use ::std::path::Path;
+
+use ::pmem_log::record::{Compression, Records};
+use ::pmem_log::RecordLog;
+
+use common::clean;
+
+fn create(path: &Path) -> RecordLog {
+    clean(path);
+    RecordLog::create(path, 2 * 1024 * 1024).unwrap()
+}
+
+fn entries(log: &RecordLog) -> Vec<Vec<u8>> { log.records().map(|e| e.unwrap().into_owned()).collect() }
+
+#[test]
+fn mixed() {
+    let json = br#"{"event":"login","user":"someone","ok":true}"#.repeat(20);
+    let mut log = create(Path::new("/tmp/test-compression-mixed.pmemlog"));
+    log.append(&json).unwrap();
+    let plain = log.len();
+
+    log.set_compression(Some(Compression::new(3))).unwrap();
+    log.append(&json).unwrap();
+    log.append("tiny").unwrap();
+    assert!(log.len() - plain < plain);
+
+    log.set_compression(None).unwrap();
+    log.append("plain").unwrap();
+
+    assert_eq!(entries(&log), vec![json.clone(), json.clone(), b"tiny".to_vec(), b"plain".to_vec()]);
+
+    // records read outside of the log are decompressed too
+    let mut data = vec![0; log.len()];
+    log.get_ref().read_at(0, &mut data).unwrap();
+    let records: Vec<_> = Records::new(&data).map(|r| r.unwrap().into_owned()).collect();
+    assert_eq!(records, vec![json.clone(), json, b"tiny".to_vec(), b"plain".to_vec()]);
+}
+
+#[test]
+fn dictionary() {
+    let dictionary = br#"{"event":"login","user":"","ok":true}"#.repeat(4);
+    let entry = br#"{"event":"login","user":"someone","ok":true}"#.repeat(2);
+
+    let mut log = create(Path::new("/tmp/test-compression-dictionary.pmemlog"));
+    log.set_compression(Some(Compression::new(3).dictionary(dictionary.clone()).min_size(0))).unwrap();
+    log.append(&entry).unwrap();
+    assert!(log.len() < entry.len());
+    assert_eq!(entries(&log), vec![entry.clone()]);
+
+    // without the dictionary the record cannot be read back
+    log.set_compression(None).unwrap();
+    let err = log.records().next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,126 @@
+extern crate pmem_log;
+
+mod common;
+
+use ::std::fs;
+use ::std::io;
+use ::std::os::unix::fs::PermissionsExt;
+use ::std::path::Path;
+use ::std::thread;
+
+use ::pmem_log::{Cursors, Log, SegmentedLog};
+
+use common::clean;
+
+fn create(path: &Path, max_cursors: usize) -> Cursors {
+    clean(path);
+    Cursors::create(path, max_cursors).unwrap()
+}
+
+#[test]
+fn commit_resume() {
+    let path = Path::new("/tmp/test-cursor-commit_resume.pmemcur");
+    {
+        let cursors = create(path, 4);
+        let a = cursors.cursor("a").unwrap();
+        assert_eq!(a.name(), "a");
+        assert_eq!(a.resume(), 0);
+        a.commit(42).unwrap();
+        cursors.cursor("b").unwrap().commit(7).unwrap();
+    }
+
+    let mut cursors = Cursors::open(path).unwrap();
+    assert_eq!(cursors.capacity(), 4);
+    assert_eq!(cursors.cursor("a").unwrap().resume(), 42);
+    assert_eq!(cursors.min_offset(), Some(7));
+    assert!(cursors.remove("b").unwrap());
+    assert!(!cursors.remove("b").unwrap());
+    assert_eq!(cursors.list(), vec![("a".to_string(), 42)]);
+}
+
+#[test]
+fn full() {
+    let cursors = create(Path::new("/tmp/test-cursor-full.pmemcur"), 1);
+    cursors.cursor("a").unwrap();
+    assert!(cursors.cursor("b").is_err());
+    assert!(cursors.cursor("").is_err());
+    assert!(cursors.cursor(&"x".repeat(pmem_log::cursor::MAX_NAME_LEN + 1)).is_err());
+}
+
+#[test]
+fn truncate_front() {
+    let path = Path::new("/tmp/test-cursor-truncate_front.pmemlog");
+    clean(path);
+    let mut log = Log::create(path, 2 * 1024 * 1024).unwrap();
+    log.append("foobarbaz").unwrap();
+
+    let cursors = create(Path::new("/tmp/test-cursor-truncate_front.pmemcur"), 4);
+    cursors.cursor("slow").unwrap().commit(3).unwrap();
+    cursors.cursor("fast").unwrap().commit(9).unwrap();
+
+    let err = cursors.truncate_front(&mut log, 6).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(log.len(), 9);
+
+    cursors.truncate_front(&mut log, 3).unwrap();
+    assert_eq!(log.len(), 6);
+    assert_eq!(cursors.cursor("slow").unwrap().resume(), 0);
+    assert_eq!(cursors.cursor("fast").unwrap().resume(), 6);
+}
+
+#[test]
+fn truncate_front_while_registering() {
+    let path = Path::new("/tmp/test-cursor-truncate_front_while_registering.pmemlog");
+    clean(path);
+    let mut log = Log::create(path, 2 * 1024 * 1024).unwrap();
+    log.append(vec![0; 100]).unwrap();
+
+    let cursors = create(Path::new("/tmp/test-cursor-truncate_front_while_registering.pmemcur"), 64);
+    cursors.cursor("first").unwrap().commit(100).unwrap();
+    thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..50 {
+                cursors.cursor(&format!("late-{}", i)).unwrap();
+            }
+        });
+        for _ in 0..50 {
+            match cursors.truncate_front(&mut log, 1) {
+                Ok(()) => {}
+                Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+            }
+        }
+    });
+    // a cursor registered at 0 is never shifted below it
+    for (name, offset) in cursors.list() {
+        assert!(offset <= log.len() as u64, "{} at {}", name, offset);
+    }
+}
+
+#[test]
+fn delete_before() {
+    let dir = Path::new("/tmp/test-cursor-delete_before");
+    clean(dir);
+    let mut log = SegmentedLog::create(dir, 2 * 1024 * 1024).unwrap();
+    let entry = vec![0; log.segment_size() / 2];
+    for _ in 0..4 {
+        log.append(&entry).unwrap();
+    }
+    assert!(log.segment_count() > 1);
+
+    let cursors = Cursors::create(dir.join("cursors.pmemcur"), 4).unwrap();
+    let cursor = cursors.cursor("reader").unwrap();
+    let end = log.len();
+    assert!(cursors.delete_before(&mut log, end).is_err());
+
+    cursor.commit(end).unwrap();
+    assert!(cursors.delete_before(&mut log, end).unwrap() > 0);
+    assert_eq!(log.segment_count(), 1);
+}
+
+#[test]
+fn create_mode() {
+    let path = Path::new("/tmp/test-cursor-create_mode.pmemcur");
+    clean(path);
+    drop(Cursors::create_mode(path, 4, 0o600).unwrap());
+    assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+}
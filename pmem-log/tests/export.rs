@@ -0,0 +1,74 @@
+extern crate pmem_log;
+
+mod common;
+
+use ::std::io;
+use ::std::path::Path;
+
+use ::pmem_log::export::{self, ExportHeader};
+use ::pmem_log::Log;
+
+use common::{clean, contents};
+
+fn create(path: &Path) -> Log {
+    clean(path);
+    Log::create(path, 2 * 1024 * 1024).unwrap()
+}
+
+#[test]
+fn round_trip() {
+    let mut log = create(Path::new("/tmp/test-export-round_trip.pmemlog"));
+    let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+    log.append(&data).unwrap();
+
+    let mut exported = Vec::new();
+    assert_eq!(log.export(&mut exported).unwrap(), data.len() as u64);
+    // header, three full blocks and a partial one, each with its crc
+    assert_eq!(exported.len(), export::HEADER_SIZE + data.len() + 4 * 4);
+
+    let header = ExportHeader::read(&mut &exported[..]).unwrap();
+    assert_eq!(header.len, data.len() as u64);
+    assert_eq!(header.capacity, log.capacity() as u64);
+
+    let path = Path::new("/tmp/test-export-round_trip-imported.pmemlog");
+    clean(path);
+    let imported = Log::import(&exported[..], path, 2 * 1024 * 1024).unwrap();
+    assert_eq!(contents(&imported), data);
+}
+
+#[test]
+fn empty() {
+    let log = create(Path::new("/tmp/test-export-empty.pmemlog"));
+    let mut exported = Vec::new();
+    log.export(&mut exported).unwrap();
+    assert_eq!(exported.len(), export::HEADER_SIZE);
+
+    let path = Path::new("/tmp/test-export-empty-imported.pmemlog");
+    clean(path);
+    assert!(Log::import(&exported[..], path, 2 * 1024 * 1024).unwrap().is_empty());
+}
+
+#[test]
+fn corrupt() {
+    let mut log = create(Path::new("/tmp/test-export-corrupt.pmemlog"));
+    log.append("foobar").unwrap();
+    let mut exported = Vec::new();
+    log.export(&mut exported).unwrap();
+
+    let path = Path::new("/tmp/test-export-corrupt-imported.pmemlog");
+    clean(path);
+
+    let mut bad = exported.clone();
+    bad[export::HEADER_SIZE + 2] ^= 1;
+    let err = Log::import(&bad[..], path, 2 * 1024 * 1024).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(!path.exists());
+
+    let err = Log::import(&exported[..exported.len() - 1], path, 2 * 1024 * 1024).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    let mut bad = exported.clone();
+    bad[20] ^= 1;
+    let err = Log::import(&bad[..], path, 2 * 1024 * 1024).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,153 @@
+extern crate pmem_log;
+
+mod common;
+
+use ::std::path::Path;
+
+use ::pmem_log::{IndexedLog, RecordLog};
+
+use common::clean;
+
+fn create(path: &Path, index: &Path) -> RecordLog {
+    for p in &[path, index] {
+        clean(p);
+    }
+    RecordLog::create(path, 2 * 1024 * 1024).unwrap()
+}
+
+#[test]
+fn seek_record() {
+    let path = Path::new("/tmp/test-index-seek_record.pmemlog");
+    let index = Path::new("/tmp/test-index-seek_record.pmemidx");
+    let mut log = IndexedLog::open(create(path, index), index, 4).unwrap();
+    for i in 0..50u32 {
+        log.append(i.to_string()).unwrap();
+    }
+    assert_eq!(log.record_count(), 50);
+
+    for n in 0..50 {
+        assert_eq!(log.get(n).unwrap().unwrap(), n.to_string().as_bytes());
+    }
+    assert!(log.get(50).is_none());
+
+    let rest: Vec<_> = log.seek_record(47).unwrap().map(|r| r.unwrap().to_vec()).collect();
+    assert_eq!(rest, vec![b"47".to_vec(), b"48".to_vec(), b"49".to_vec()]);
+    assert_eq!(log.seek_record(50).unwrap().count(), 0);
+    assert!(log.seek_record(51).is_err());
+}
+
+#[test]
+fn catch_up() {
+    let path = Path::new("/tmp/test-index-catch_up.pmemlog");
+    let index = Path::new("/tmp/test-index-catch_up.pmemidx");
+    {
+        let mut log = IndexedLog::open(create(path, index), index, 4).unwrap();
+        log.append_many(&["a", "b", "c", "d", "e"]).unwrap();
+    }
+    {
+        // appended behind the back of the index
+        let mut log = RecordLog::open(path).unwrap();
+        log.append_many(&["f", "g", "h", "i"]).unwrap();
+    }
+
+    let log = IndexedLog::open(RecordLog::open(path).unwrap(), index, 4).unwrap();
+    assert_eq!(log.record_count(), 9);
+    assert_eq!(log.get(8).unwrap().unwrap(), &b"i"[..]);
+}
+
+#[test]
+fn rebuild() {
+    let path = Path::new("/tmp/test-index-rebuild.pmemlog");
+    let index = Path::new("/tmp/test-index-rebuild.pmemidx");
+    {
+        let mut log = IndexedLog::open(create(path, index), index, 2).unwrap();
+        for i in 0..10u32 {
+            log.append(format!("entry {}", i)).unwrap();
+        }
+    }
+    {
+        // drop the first three records, the index is now stale
+        let log = RecordLog::open(path).unwrap();
+        let upto = log.records().take(3).map(|r| 4 + r.unwrap().len()).sum();
+        let mut raw = log.into_inner();
+        raw.truncate_front(upto).unwrap();
+    }
+
+    let log = IndexedLog::open(RecordLog::open(path).unwrap(), index, 2).unwrap();
+    assert_eq!(log.record_count(), 7);
+    assert_eq!(log.get(0).unwrap().unwrap(), &b"entry 3"[..]);
+
+    // another interval rebuilds it too
+    let log = IndexedLog::open(log.into_inner(), index, 3).unwrap();
+    assert_eq!(log.get(6).unwrap().unwrap(), &b"entry 9"[..]);
+}
+
+#[test]
+fn rebuild_shared_prefix() {
+    let path = Path::new("/tmp/test-index-rebuild_shared_prefix.pmemlog");
+    let index = Path::new("/tmp/test-index-rebuild_shared_prefix.pmemidx");
+    // the same length and the same first 72 bytes for all of them
+    let entry = |i: u32| {
+        format!(r#"{{"type":"event","source":"sensor","version":1,"padding":"--------","id":{}}}"#, i)
+    };
+    {
+        let mut log = IndexedLog::open(create(path, index), index, 2).unwrap();
+        for i in 0..10u32 {
+            log.append(entry(i)).unwrap();
+        }
+    }
+    {
+        // the new first record starts with the same 64 bytes as the old one
+        let log = RecordLog::open(path).unwrap();
+        let upto = 4 + log.records().next().unwrap().unwrap().len();
+        let mut raw = log.into_inner();
+        raw.truncate_front(upto).unwrap();
+    }
+
+    let log = IndexedLog::open(RecordLog::open(path).unwrap(), index, 2).unwrap();
+    assert_eq!(log.record_count(), 9);
+    for n in 0..9 {
+        assert_eq!(log.get(n).unwrap().unwrap(), entry(n as u32 + 1).as_bytes());
+    }
+}
+
+#[test]
+fn rebuild_after_rewind() {
+    let path = Path::new("/tmp/test-index-rebuild_after_rewind.pmemlog");
+    let index = Path::new("/tmp/test-index-rebuild_after_rewind.pmemidx");
+    {
+        let mut log = IndexedLog::open(create(path, index), index, 2).unwrap();
+        log.append("header").unwrap();
+        for i in 0..10u32 {
+            log.append(format!("entry {}", i)).unwrap();
+        }
+    }
+    {
+        // the same first record, then longer ones
+        let mut raw = RecordLog::open(path).unwrap().into_inner();
+        raw.rewind();
+        let mut log = RecordLog::new(raw);
+        log.append("header").unwrap();
+        for i in 0..10u32 {
+            log.append(format!("longer entry {}", i)).unwrap();
+        }
+    }
+
+    let log = IndexedLog::open(RecordLog::open(path).unwrap(), index, 2).unwrap();
+    assert_eq!(log.record_count(), 11);
+    for n in 1..11 {
+        assert_eq!(log.get(n).unwrap().unwrap(), format!("longer entry {}", n - 1).as_bytes());
+    }
+    {
+        // the same first record, and fewer of the others
+        let mut raw = log.into_inner().into_inner();
+        raw.rewind();
+        let mut log = RecordLog::new(raw);
+        log.append("header").unwrap();
+        log.append("entry 0").unwrap();
+    }
+
+    let log = IndexedLog::open(RecordLog::open(path).unwrap(), index, 2).unwrap();
+    assert_eq!(log.record_count(), 2);
+    assert_eq!(log.get(1).unwrap().unwrap(), &b"entry 0"[..]);
+}
@@ -0,0 +1,69 @@
+extern crate pmem_log;
+
+mod common;
+
+use ::std::io;
+use ::std::path::Path;
+
+use ::pmem_log::{Log, RecordLog};
+
+use common::clean;
+
+fn create(path: &Path) -> RecordLog {
+    clean(path);
+    RecordLog::create(path, 2 * 1024 * 1024).unwrap()
+}
+
+#[test]
+fn records() {
+    let path = Path::new("/tmp/test-record-records.pmemlog");
+    {
+        let mut p = create(path);
+        assert!(p.is_empty());
+        p.append("dez").unwrap();
+        p.append("").unwrap();
+        p.append_many(&["foo", "barbaz"]).unwrap();
+        assert_eq!(p.len(), 4 * 4 + 12);
+    }
+
+    let p = RecordLog::open(path).unwrap();
+    let records: Vec<_> = p.records().map(|r| r.unwrap()).collect();
+    assert_eq!(records, vec![&b"dez"[..], b"", b"foo", b"barbaz"]);
+}
+
+#[test]
+fn empty() {
+    let p = create(Path::new("/tmp/test-record-empty.pmemlog"));
+    assert_eq!(p.records().count(), 0);
+}
+
+#[test]
+fn torn_frame() {
+    let path = Path::new("/tmp/test-record-torn_frame.pmemlog");
+    let mut p = create(path);
+    p.append("complete").unwrap();
+
+    // a header promising more bytes than there are
+    let mut log = p.into_inner();
+    log.append([100, 0, 0, 0, 1, 2]).unwrap();
+    let p = RecordLog::new(log);
+
+    let mut records = p.records();
+    assert_eq!(records.next().unwrap().unwrap(), &b"complete"[..]);
+    assert_eq!(records.offset(), 12);
+    assert_eq!(records.next().unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert!(records.next().is_none());
+}
+
+#[test]
+fn torn_header() {
+    let path = Path::new("/tmp/test-record-torn_header.pmemlog");
+    clean(path);
+    let mut log = Log::create(path, 2 * 1024 * 1024).unwrap();
+    log.append([1, 0]).unwrap();
+
+    let p = RecordLog::new(log);
+    let records: Vec<_> = p.records().collect();
+    assert_eq!(records.len(), 1);
+    assert!(records[0].is_err());
+}
@@ -0,0 +1,93 @@
+extern crate pmem_log;
+
+mod common;
+
+use ::std::fs;
+use ::std::io;
+use ::std::os::unix::fs::PermissionsExt;
+use ::std::os::unix::fs::FileExt;
+use ::std::path::Path;
+
+use ::pmem_log::RingLog;
+
+use common::clean;
+
+fn create(path: &Path, size: usize) -> RingLog {
+    clean(path);
+    RingLog::create(path, size).unwrap()
+}
+
+fn entries(ring: &RingLog) -> Vec<Vec<u8>> { ring.iter().map(|e| e.unwrap()).collect() }
+
+#[test]
+fn append_iter() {
+    let path = Path::new("/tmp/test-ring-append_iter.pmemring");
+    {
+        let mut ring = create(path, 4096);
+        assert!(ring.is_empty());
+        ring.append("foo").unwrap();
+        ring.append("").unwrap();
+        ring.append("barbaz").unwrap();
+        assert_eq!(ring.len(), 3 * 8 + 9);
+    }
+
+    let ring = RingLog::open(path).unwrap();
+    assert_eq!(entries(&ring), vec![b"foo".to_vec(), Vec::new(), b"barbaz".to_vec()]);
+}
+
+#[test]
+fn wrap_around() {
+    let path = Path::new("/tmp/test-ring-wrap_around.pmemring");
+    {
+        let mut ring = create(path, 64 + 100);
+        assert_eq!(ring.capacity(), 100);
+        // frames of 8 + 12 bytes, the ring holds 5 of them and keeps wrapping
+        for i in 0..23u8 {
+            ring.append([i; 12]).unwrap();
+            assert!(ring.len() <= ring.capacity());
+        }
+        assert_eq!(entries(&ring), (18..23u8).map(|i| vec![i; 12]).collect::<Vec<_>>());
+
+        // an odd size frame straddles the end of the data area
+        ring.append([99; 30]).unwrap();
+    }
+
+    let ring = RingLog::open(path).unwrap();
+    let entries = entries(&ring);
+    assert_eq!(entries.last().unwrap(), &vec![99; 30]);
+    assert_eq!(entries[0], vec![20; 12]);
+}
+
+#[test]
+fn too_large() {
+    let mut ring = create(Path::new("/tmp/test-ring-too_large.pmemring"), 64 + 100);
+    assert!(ring.append([0; 93]).is_err());
+    ring.append([0; 92]).unwrap();
+    ring.append([1; 92]).unwrap();
+    assert_eq!(entries(&ring), vec![vec![1; 92]]);
+
+    ring.clear().unwrap();
+    assert!(ring.is_empty());
+    assert!(entries(&ring).is_empty());
+}
+
+#[test]
+fn corrupt_capacity() {
+    let path = Path::new("/tmp/test-ring-corrupt_capacity.pmemring");
+    drop(create(path, 4096));
+
+    let file = fs::OpenOptions::new().write(true).open(path).unwrap();
+    for capacity in [0u64, 8] {
+        file.write_all_at(&capacity.to_le_bytes(), 8).unwrap();
+        let err = RingLog::open(path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn create_mode() {
+    let path = Path::new("/tmp/test-ring-create_mode.pmemring");
+    clean(path);
+    drop(RingLog::create_mode(path, 4096, 0o600).unwrap());
+    assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+}
@@ -0,0 +1,112 @@
+extern crate pmem_log;
+
+mod common;
+
+use ::std::fs;
+use ::std::io;
+use ::std::ops::ControlFlow;
+use ::std::path::Path;
+
+use ::pmem_log::SegmentedLog;
+
+use common::clean;
+
+const SEGMENT_SIZE: usize = 2 * 1024 * 1024;
+
+fn create(dir: &Path) -> SegmentedLog {
+    clean(dir);
+    SegmentedLog::create(dir, SEGMENT_SIZE).unwrap()
+}
+
+fn contents(log: &SegmentedLog) -> Vec<u8> {
+    let mut data = Vec::new();
+    log.walk(0, |offset, chunk| {
+        assert_eq!(offset, log.start() + data.len() as u64);
+        data.extend_from_slice(chunk);
+        ControlFlow::<io::Error>::Continue(())
+    }).unwrap();
+    data
+}
+
+#[test]
+fn create_twice() {
+    let dir = Path::new("/tmp/test-segmented-create_twice");
+    let _log = create(dir);
+    assert!(SegmentedLog::create(dir, SEGMENT_SIZE).is_err());
+}
+
+#[test]
+fn append() {
+    let mut log = create(Path::new("/tmp/test-segmented-append"));
+    assert!(log.is_empty());
+    assert_eq!(log.append("foo").unwrap(), 0);
+    assert_eq!(log.append("bar").unwrap(), 3);
+    assert_eq!(log.len(), 6);
+    assert_eq!(log.segment_count(), 1);
+}
+
+#[test]
+fn roll_over() {
+    let dir = Path::new("/tmp/test-segmented-roll_over");
+    let entry = vec![7; 700 * 1024];
+    {
+        let mut log = create(dir);
+        let mut offset = 0;
+        for _ in 0..5 {
+            assert_eq!(log.append(&entry).unwrap(), offset);
+            offset += entry.len() as u64;
+        }
+        assert!(log.segment_count() > 1);
+        assert_eq!(log.len(), offset);
+    }
+
+    let mut log = SegmentedLog::open(dir).unwrap();
+    assert_eq!(log.len(), 5 * entry.len() as u64);
+    assert_eq!(contents(&log), vec![7; 5 * entry.len()]);
+    log.append("after-open").unwrap();
+}
+
+#[test]
+fn too_large() {
+    let mut log = create(Path::new("/tmp/test-segmented-too_large"));
+    let entry = vec![0; SEGMENT_SIZE];
+    assert_eq!(log.append(&entry).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn too_large_after_append() {
+    let dir = Path::new("/tmp/test-segmented-too_large_after_append");
+    let mut log = create(dir);
+    log.append("foo").unwrap();
+    let entry = vec![0; SEGMENT_SIZE];
+    assert_eq!(log.append(&entry).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+
+    // still rolls over
+    let entry = vec![1; 700 * 1024];
+    for _ in 0..3 {
+        log.append(&entry).unwrap();
+    }
+    assert_eq!(log.segment_count(), 2);
+    assert_eq!(log.len(), 3 + 3 * entry.len() as u64);
+}
+
+#[test]
+fn delete_before() {
+    let mut log = create(Path::new("/tmp/test-segmented-delete_before"));
+    let entry = vec![1; 700 * 1024];
+    let offsets: Vec<u64> = (0..6).map(|_| log.append(&entry).unwrap()).collect();
+    let segments = log.segment_count();
+
+    assert_eq!(log.delete_before(0).unwrap(), 0);
+    let deleted = log.delete_before(offsets[4]).unwrap();
+    assert!(deleted > 0);
+    assert_eq!(log.segment_count(), segments - deleted);
+    assert!(log.start() <= offsets[4]);
+    assert_eq!(contents(&log).len() as u64, log.len() - log.start());
+
+    // never deletes the active segment
+    let end = log.len();
+    log.delete_before(end).unwrap();
+    assert_eq!(log.segment_count(), 1);
+}
@@ -0,0 +1,116 @@
+extern crate pmem_log;
+
+mod common;
+
+use ::std::ops::ControlFlow;
+use ::std::panic;
+use ::std::path::Path;
+use ::std::thread;
+use ::std::time::Duration;
+
+use ::pmem_log::{Log, SharedLog};
+
+use common::clean;
+
+fn create(path: &Path, size: usize) -> SharedLog {
+    clean(path);
+    SharedLog::new(Log::create(path, size).unwrap())
+}
+
+#[test]
+fn append() {
+    let log = create(Path::new("/tmp/test-shared-append.pmemlog"), 2 * 1024 * 1024);
+    assert_eq!(log.append("foo").unwrap(), 0);
+    assert_eq!(log.append("barbaz").unwrap(), 3);
+    assert_eq!(log.len(), 9);
+}
+
+#[test]
+fn concurrent_appends() {
+    let log = create(Path::new("/tmp/test-shared-concurrent_appends.pmemlog"), 8 * 1024 * 1024);
+
+    let threads: Vec<_> = (0..8u8)
+        .map(|t| {
+            let log = log.clone();
+            thread::spawn(move || {
+                (0..100u8).map(|i| (log.append([t, i, t, i]).unwrap(), [t, i, t, i])).collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut written: Vec<_> = threads.into_iter().flat_map(|t| t.join().unwrap()).collect();
+    written.sort();
+    assert_eq!(log.len(), 8 * 100 * 4);
+
+    let mut data = Vec::new();
+    log.walk(0, |chunk| {
+        data.extend_from_slice(chunk);
+        ControlFlow::<()>::Continue(())
+    }).unwrap();
+    for (i, &(offset, entry)) in written.iter().enumerate() {
+        assert_eq!(offset, 4 * i as u64);
+        assert_eq!(&data[offset as usize..offset as usize + 4], &entry[..]);
+    }
+
+    let log = log.try_unwrap().ok().unwrap();
+    assert_eq!(log.len(), 8 * 100 * 4);
+}
+
+#[test]
+fn full() {
+    let log = create(Path::new("/tmp/test-shared-full.pmemlog"), 2 * 1024 * 1024);
+    let entry = vec![0; log.capacity() + 1];
+    assert!(log.append(&entry).is_err());
+    assert_eq!(log.append("still works").unwrap(), 0);
+}
+
+#[test]
+fn subscribe() {
+    let log = create(Path::new("/tmp/test-shared-subscribe.pmemlog"), 2 * 1024 * 1024);
+    log.append("foo").unwrap();
+
+    let mut sub = log.subscribe(1);
+    assert_eq!(sub.next(), Some((1, b"oo".to_vec())));
+    assert_eq!(sub.try_next(), None);
+    assert_eq!(sub.next_timeout(Duration::from_millis(10)), None);
+
+    let appender = {
+        let log = log.clone();
+        thread::spawn(move || {
+            log.append("bar").unwrap();
+        })
+    };
+    assert_eq!(sub.next(), Some((3, b"bar".to_vec())));
+    assert_eq!(sub.offset(), 6);
+    appender.join().unwrap();
+
+    drop(log);
+    assert_eq!(sub.next(), None);
+}
+
+#[test]
+fn subscribe_channel() {
+    let log = create(Path::new("/tmp/test-shared-subscribe_channel.pmemlog"), 2 * 1024 * 1024);
+    let rx = log.subscribe_channel(0);
+    for i in 0..10u8 {
+        log.append([i]).unwrap();
+    }
+    drop(log);
+
+    let data: Vec<u8> = rx.iter().flat_map(|(_, chunk)| chunk).collect();
+    assert_eq!(data, (0..10u8).collect::<Vec<_>>());
+}
+
+#[test]
+fn leader_panics() {
+    let log = create(Path::new("/tmp/test-shared-leader_panics.pmemlog"), 2 * 1024 * 1024);
+    log.append("foo").unwrap();
+    // a panic while walking poisons the log, the next leader panics on it
+    let walk = panic::catch_unwind(|| log.walk(4096, |_| -> ControlFlow<()> { panic!("walk") }));
+    assert!(walk.is_err());
+    assert!(panic::catch_unwind(|| log.append("bar")).is_err());
+
+    // the leader that panicked stepped down, the next appender does not wait for it
+    let other = log.clone();
+    let append = thread::spawn(move || panic::catch_unwind(|| other.append("bar")).is_err());
+    assert!(append.join().unwrap());
+}
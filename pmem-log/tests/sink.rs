@@ -0,0 +1,71 @@
+extern crate pmem_log;
+#[cfg(feature = "log")]
+extern crate log;
+
+mod common;
+
+use ::std::path::Path;
+
+use ::pmem_log::{RecordLog, SegmentedLog, Sink};
+
+use common::clean;
+
+fn create(path: &Path) -> RecordLog {
+    clean(path);
+    RecordLog::create(path, 2 * 1024 * 1024).unwrap()
+}
+
+fn lines(path: &Path) -> Vec<String> {
+    RecordLog::open(path)
+        .unwrap()
+        .records()
+        .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+        .collect()
+}
+
+#[test]
+fn record() {
+    let path = Path::new("/tmp/test-sink-record.pmemlog");
+    {
+        let sink = Sink::new(create(path));
+        sink.record("INFO", "app", format_args!("started {}", 1));
+        sink.record("ERROR", "app::db", format_args!("oops"));
+    }
+
+    let lines = lines(path);
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(" INFO  app: started 1"), "{}", lines[0]);
+    assert!(lines[1].ends_with(" ERROR app::db: oops"), "{}", lines[1]);
+}
+
+#[test]
+fn rotating() {
+    let dir = Path::new("/tmp/test-sink-rotating");
+    clean(dir);
+    let log = SegmentedLog::create(dir, 2 * 1024 * 1024).unwrap();
+    let line = "x".repeat(log.segment_size() / 4);
+    let sink = Sink::rotating(log, 2);
+    for _ in 0..20 {
+        sink.append(&line).unwrap();
+    }
+    drop(sink);
+
+    assert_eq!(SegmentedLog::open(dir).unwrap().segment_count(), 2);
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_facade() {
+    use log::Log;
+
+    let path = Path::new("/tmp/test-sink-log_facade.pmemlog");
+    let sink = Sink::new(create(path));
+    sink.log(&log::Record::builder()
+                  .level(log::Level::Warn)
+                  .target("facade")
+                  .args(format_args!("careful"))
+                  .build());
+    drop(sink);
+
+    assert!(lines(path)[0].ends_with(" WARN  facade: careful"));
+}
@@ -0,0 +1,129 @@
+extern crate pmem_log;
+
+mod common;
+
+use ::std::fs;
+use ::std::io;
+use ::std::path::Path;
+
+use ::pmem_log::{Log, Lsn, Wal};
+
+use common::clean;
+
+fn create(path: &Path) -> Wal {
+    clean(path);
+    Wal::create(path, 2 * 1024 * 1024).unwrap()
+}
+
+fn replay(wal: &Wal, from: Lsn) -> Vec<(u64, u8, Vec<u8>)> {
+    wal.replay(from).map(|r| r.unwrap()).map(|r| (r.lsn.0, r.kind, r.data.to_vec())).collect()
+}
+
+#[test]
+fn append_replay() {
+    let path = Path::new("/tmp/test-wal-append_replay.pmemlog");
+    {
+        let mut wal = create(path);
+        assert_eq!(wal.append(1, "foo").unwrap(), Lsn(1));
+        assert_eq!(wal.append(2, "bar").unwrap(), Lsn(2));
+        assert_eq!(wal.append(1, "").unwrap(), Lsn(3));
+        assert!(wal.append(0, "reserved").is_err());
+    }
+
+    let wal = Wal::open(path).unwrap();
+    assert_eq!(wal.next_lsn(), Lsn(4));
+    assert_eq!(replay(&wal, Lsn(0)),
+               vec![(1, 1, b"foo".to_vec()), (2, 2, b"bar".to_vec()), (3, 1, Vec::new())]);
+    assert_eq!(replay(&wal, Lsn(3)), vec![(3, 1, Vec::new())]);
+}
+
+#[test]
+fn checkpoint() {
+    let path = Path::new("/tmp/test-wal-checkpoint.pmemlog");
+    {
+        let mut wal = create(path);
+        for i in 0..10u8 {
+            wal.append(1, [i]).unwrap();
+        }
+        let before = wal.get_ref().len();
+        wal.checkpoint(Lsn(8)).unwrap();
+        assert!(wal.get_ref().len() < before);
+        assert_eq!(replay(&wal, Lsn(0)).len(), 3);
+
+        assert!(wal.checkpoint(Lsn(100)).is_err());
+        wal.checkpoint(wal.next_lsn()).unwrap();
+        assert!(replay(&wal, Lsn(0)).is_empty());
+    }
+
+    let mut wal = Wal::open(path).unwrap();
+    assert_eq!(wal.checkpoint_lsn(), Lsn(12));
+    assert_eq!(wal.append(1, "next").unwrap(), Lsn(13));
+}
+
+#[test]
+fn corrupt_tail() {
+    let path = Path::new("/tmp/test-wal-corrupt_tail.pmemlog");
+    {
+        let mut wal = create(path);
+        wal.append(1, "good").unwrap();
+        let mut log = wal.into_inner();
+        log.append("garbage that is not a frame").unwrap();
+    }
+
+    let mut wal = Wal::open(path).unwrap();
+    assert!(wal.has_corrupt_tail());
+    let records: Vec<_> = wal.replay(Lsn(0)).collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].as_ref().unwrap().data, b"good");
+    assert_eq!(records[1].as_ref().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    assert_eq!(wal.append(1, "after").unwrap(), Lsn(2));
+    assert!(!wal.has_corrupt_tail());
+    assert_eq!(replay(&wal, Lsn(0)).len(), 2);
+}
+
+#[test]
+fn damaged() {
+    let path = Path::new("/tmp/test-wal-damaged.pmemlog");
+    let frames = {
+        let mut wal = create(path);
+        wal.append(1, "first").unwrap();
+        wal.append(1, "second").unwrap();
+        let log = wal.into_inner();
+        let mut frames = vec![0; log.len()];
+        log.read_at(0, &mut frames).unwrap();
+        frames
+    };
+    {
+        // a corrupt frame between the two valid ones
+        fs::remove_file(path).unwrap();
+        let mut log = Log::create(path, 2 * 1024 * 1024).unwrap();
+        log.append(&frames[..17 + 5]).unwrap();
+        log.append("garbage that is not a frame").unwrap();
+        log.append(&frames[17 + 5..]).unwrap();
+    }
+
+    let mut wal = Wal::open(path).unwrap();
+    let len = wal.get_ref().len();
+    assert!(wal.is_damaged());
+    assert!(!wal.has_corrupt_tail());
+    assert_eq!(wal.append(1, "third").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(wal.get_ref().len(), len);
+
+    assert_eq!(wal.repair().unwrap(), len - 17 - 5);
+    assert!(!wal.is_damaged());
+    assert_eq!(wal.append(1, "third").unwrap(), Lsn(2));
+}
+
+#[test]
+fn not_a_wal() {
+    let path = Path::new("/tmp/test-wal-not_a_wal.pmemlog");
+    clean(path);
+    let mut log = Log::create(path, 2 * 1024 * 1024).unwrap();
+    log.append("some other data").unwrap();
+
+    let mut wal = Wal::new(log);
+    assert!(wal.is_damaged());
+    assert!(wal.append(1, "record").is_err());
+    assert_eq!(wal.get_ref().len(), 15);
+}
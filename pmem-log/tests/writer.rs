@@ -0,0 +1,61 @@
+extern crate pmem_log;
+
+mod common;
+
+use ::std::io::Write;
+use ::std::path::Path;
+
+use ::pmem_log::{Log, LogWriter};
+
+use common::{clean, contents};
+
+fn create(path: &Path) -> Log {
+    clean(path);
+    Log::create(path, 2 * 1024 * 1024).unwrap()
+}
+
+#[test]
+fn write() {
+    let mut log = create(Path::new("/tmp/test-writer-write.pmemlog"));
+    write!(log, "foo {}", 42).unwrap();
+    log.write_all(b"bar").unwrap();
+    log.flush().unwrap();
+    assert_eq!(contents(&log), b"foo 42bar");
+}
+
+#[test]
+fn buffered() {
+    let log = create(Path::new("/tmp/test-writer-buffered.pmemlog"));
+    let mut w = LogWriter::with_capacity(8, log);
+    w.write_all(b"foo").unwrap();
+    w.write_all(b"bar").unwrap();
+    assert_eq!(w.buffered(), 6);
+    assert!(w.get_ref().is_empty());
+
+    w.flush().unwrap();
+    assert_eq!(w.buffered(), 0);
+    assert_eq!(contents(w.get_ref()), b"foobar");
+
+    // past the capacity the buffer is flushed first
+    w.write_all(b"dez").unwrap();
+    w.write_all(b"zzzzzz").unwrap();
+    assert_eq!(contents(w.get_ref()), b"foobardez");
+
+    // larger than the capacity goes straight to the log
+    w.write_all(b"0123456789").unwrap();
+    assert_eq!(contents(w.get_ref()), b"foobardezzzzzzz0123456789");
+
+    w.write_all(b"end").unwrap();
+    let log = w.into_inner().ok().unwrap();
+    assert_eq!(contents(&log), b"foobardezzzzzzz0123456789end");
+}
+
+#[test]
+fn flush_on_drop() {
+    let path = Path::new("/tmp/test-writer-flush_on_drop.pmemlog");
+    {
+        let mut w = LogWriter::new(create(path));
+        w.write_all(b"foo").unwrap();
+    }
+    assert_eq!(contents(&Log::open(path).unwrap()), b"foo");
+}
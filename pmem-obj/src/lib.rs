@@ -7,9 +7,10 @@
 //! >
 //! > The official **libpmemobj** documentation can be found at: [http://pmem.io/nvml/libpmemobj/](http://pmem.io/nvml/libpmemobj/)
 
+extern crate pmem;
 extern crate pmemobj_sys;
 extern crate libc;
 
 pub mod objpool;
 
-pub use objpool::ObjPool;
+pub use objpool::{ObjPool, ObjPoolOptions};
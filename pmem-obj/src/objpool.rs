@@ -1,8 +1,10 @@
 use ::std::ffi::{CString, CStr};
 use ::std::path::Path;
+use ::std::ptr;
 use ::std::io;
 
 use ::libc::{size_t, mode_t};
+use ::pmem::error::{Error, path_to_cstring};
 
 use pmemobj_sys::{self as ffi, PMEMobjpool};
 
@@ -18,55 +20,191 @@ fn errormsg() -> Option<String> {
     }
 }
 
+/// Captures the error of the failed call `operation` on the pool at `path`
+fn error(operation: &'static str, path: &Path) -> Error {
+    Error::last_os_error(operation).with_message(errormsg()).with_path(path)
+}
+
+/// Converts `layout` to pass it to the call `operation` on the pool at `path`
+fn layout_to_cstring(operation: &'static str, path: &Path, layout: String) -> Result<CString, Error> {
+    CString::new(layout).map_err(|_| {
+        Error::new(io::ErrorKind::InvalidInput, operation, "The layout contains a NUL byte").with_path(path)
+    })
+}
+
 pub struct ObjPool {
     inner: *mut PMEMobjpool,
 }
 
 
 impl ObjPool {
-    pub fn open<P: AsRef<Path>, S: Into<String>>(path: P, layout: S) -> Result<Self, io::Error> {
-        let path = CString::new(path.as_ref().to_str().unwrap()).unwrap();
-        let layout = CString::new(layout.into()).unwrap();
+    /// Opens an existent memory pool, whatever its layout
+    pub fn open_no_layout<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let cpath = path_to_cstring("pmemobj_open", path)?;
 
-        let objpool = unsafe { ffi::pmemobj_open(path.as_ptr(), layout.as_ptr()) };
+        let objpool = unsafe { ffi::pmemobj_open(cpath.as_ptr(), ptr::null()) };
 
         if objpool.is_null() {
-            let err = io::Error::last_os_error();
-            if err.kind() == io::ErrorKind::InvalidInput {
-                if let Some(msg) = errormsg() {
-                    return Err(io::Error::new(io::ErrorKind::Other, msg));
-                }
-            }
-            Err(err)
+            Err(error("pmemobj_open", path))
+        } else {
+            Ok(ObjPool { inner: objpool })
+        }
+    }
+
+    pub fn open<P: AsRef<Path>, S: Into<String>>(path: P, layout: S) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let cpath = path_to_cstring("pmemobj_open", path)?;
+        let layout = layout_to_cstring("pmemobj_open", path, layout.into())?;
+
+        let objpool = unsafe { ffi::pmemobj_open(cpath.as_ptr(), layout.as_ptr()) };
+
+        if objpool.is_null() {
+            Err(error("pmemobj_open", path))
         } else {
             Ok(ObjPool { inner: objpool })
         }
     }
 
-    pub fn create<P: AsRef<Path>, S: Into<String>>(path: P,
-                                                   layout: S,
-                                                   size: usize)
-                                                   -> Result<Self, io::Error> {
-        let path = CString::new(path.as_ref().to_str().unwrap()).unwrap();
-        let layout = CString::new(layout.into()).unwrap();
+    pub fn create<P: AsRef<Path>, S: Into<String>>(path: P, layout: S, size: usize) -> Result<Self, Error> {
+        ObjPool::create_mode(path.as_ref(), layout.into(), size, 0o666)
+    }
 
-        let mode = 0o666;
+    fn create_mode(path: &Path, layout: String, size: usize, mode: u32) -> Result<Self, Error> {
+        let cpath = path_to_cstring("pmemobj_create", path)?;
+        let layout = layout_to_cstring("pmemobj_create", path, layout)?;
 
         let objpool =
-            unsafe { ffi::pmemobj_create(path.as_ptr(), layout.as_ptr(), size as size_t, mode as mode_t) };
+            unsafe { ffi::pmemobj_create(cpath.as_ptr(), layout.as_ptr(), size as size_t, mode as mode_t) };
 
         if objpool.is_null() {
-            let err = io::Error::last_os_error();
-            if err.kind() == io::ErrorKind::InvalidInput {
-                if let Some(msg) = errormsg() {
-                    return Err(io::Error::new(io::ErrorKind::Other, msg));
-                }
-            }
-            Err(err)
+            Err(error("pmemobj_create", path))
         } else {
             Ok(ObjPool { inner: objpool })
         }
     }
+
+    /// Options to open or create a pool, see `ObjPoolOptions`
+    pub fn options() -> ObjPoolOptions { ObjPoolOptions::new() }
+
+    /// Check consistency of the memory pool
+    ///
+    /// If `layout` is given, we will also verify it matches the layout used when the pool was created.
+    pub fn check<P: AsRef<Path>>(path: P, layout: Option<&str>) -> Result<bool, Error> {
+        let path = path.as_ref();
+        let cpath = path_to_cstring("pmemobj_check", path)?;
+        let layout = layout.map(|layout| layout_to_cstring("pmemobj_check", path, layout.to_string()))
+                           .transpose()?;
+        let layout_p = layout.as_ref().map_or(ptr::null(), |layout| layout.as_ptr());
+
+        let r = unsafe { ffi::pmemobj_check(cpath.as_ptr(), layout_p) };
+        match r {
+            1 => Ok(true),
+            0 => Ok(false),
+            -1 => Err(error("pmemobj_check", path)),
+            r => {
+                Err(Error::new(io::ErrorKind::Other,
+                               "pmemobj_check",
+                               format!("Invalid return value, expected 1, 0 or -1 but received {}", r))
+                        .with_path(path))
+            }
+        }
+    }
+}
+
+/// Options to open or create an `ObjPool`, in the style of `std::fs::OpenOptions`
+///
+/// ```no_run
+/// # use pmem_obj::ObjPoolOptions;
+/// let mut options = ObjPoolOptions::new();
+/// options.create(true).layout("my_layout").size(64 * 1024 * 1024).mode(0o600);
+/// let pool = options.open("/mnt/pmem/objects").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ObjPoolOptions {
+    layout: Option<String>,
+    size: usize,
+    mode: u32,
+    create: bool,
+    create_new: bool,
+}
+
+impl ObjPoolOptions {
+    /// Options to open an existing pool, whatever its layout
+    pub fn new() -> Self { ObjPoolOptions { layout: None, size: 0, mode: 0o666, create: false, create_new: false } }
+
+    /// The layout of the pool
+    ///
+    /// An existing pool must have been created with the same layout, a new pool is created with it.
+    /// Without a layout any pool can be opened, and pools are created with an empty one.
+    pub fn layout<S: Into<String>>(&mut self, layout: S) -> &mut Self {
+        self.layout = Some(layout.into());
+        self
+    }
+
+    /// The size of the pool to create, required with `create()` or `create_new()`
+    pub fn size(&mut self, size: usize) -> &mut Self {
+        self.size = size;
+        self
+    }
+
+    /// The permissions of a created pool file, `0o666` by default, the process umask applies
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Creates the pool if it does not exist, opens it otherwise
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Creates the pool, failing if the file already exists
+    ///
+    /// Takes precedence over `create()`.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    fn open_existing(&self, path: &Path) -> Result<ObjPool, Error> {
+        match self.layout {
+            Some(ref layout) => ObjPool::open(path, layout.as_str()),
+            None => ObjPool::open_no_layout(path),
+        }
+    }
+
+    fn create_pool(&self, path: &Path) -> Result<ObjPool, Error> {
+        ObjPool::create_mode(path, self.layout.clone().unwrap_or_default(), self.size, self.mode)
+    }
+
+    /// Opens the pool at `path` with these options
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<ObjPool, Error> {
+        let path = path.as_ref();
+        // with a size of 0 the library formats an existing file in place instead of failing
+        if (self.create || self.create_new) && self.size == 0 {
+            return Err(Error::new(io::ErrorKind::InvalidInput, "pmemobj_create", "The size of the pool to create is 0")
+                .with_path(path));
+        }
+        if self.create_new {
+            return self.create_pool(path);
+        }
+        match self.open_existing(path) {
+            Err(ref err) if self.create && err.kind() == io::ErrorKind::NotFound => {
+                match self.create_pool(path) {
+                    // created by someone else in the meantime
+                    Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => self.open_existing(path),
+                    r => r,
+                }
+            }
+            r => r,
+        }
+    }
+}
+
+impl Default for ObjPoolOptions {
+    fn default() -> Self { ObjPoolOptions::new() }
 }
 
 
@@ -1,9 +1,12 @@
 extern crate pmem_obj;
 
+use ::std::ffi::OsStr;
 use ::std::fs;
+use ::std::io;
+use ::std::os::unix::ffi::OsStrExt;
 use ::std::path::Path;
 
-use ::pmem_obj::ObjPool;
+use ::pmem_obj::{ObjPool, ObjPoolOptions};
 
 
 // #[test]
@@ -30,3 +33,57 @@ fn open() {
 
     let _p = ObjPool::open(path, "").unwrap();
 }
+
+#[test]
+fn non_utf8_path() {
+    // A missing pool, see above about closing pools
+    let path = Path::new(OsStr::from_bytes(b"/tmp/test-non_utf8_path-missing-\xff\xfe.pmemobj"));
+    let err = ObjPool::open(path, "").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert_eq!(err.path(), Some(path));
+    assert_eq!(ObjPool::check(path, None).unwrap_err().path(), Some(path));
+}
+
+#[test]
+fn nul_in_path_or_layout() {
+    let path = "/tmp/test-nul\0in_path.pmemobj";
+    let err = ObjPool::create(path, "", 10 * 1024 * 1024).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.operation(), "pmemobj_create");
+    assert_eq!(ObjPool::open_no_layout(path).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+
+    let path = "/tmp/test-nul_in_layout.pmemobj";
+    let err = ObjPool::create(path, "my\0layout", 10 * 1024 * 1024).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.message(), Some("The layout contains a NUL byte"));
+    assert_eq!(ObjPool::check(path, Some("my\0layout")).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert!(!Path::new(path).exists());
+}
+
+#[test]
+fn options_missing() {
+    // Only missing pools, see above about closing pools
+    let path = Path::new("/tmp/test-options_missing.pmemobj");
+    let err = ObjPool::options().layout("my_layout").open(path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert_eq!(err.operation(), "pmemobj_open");
+    assert_eq!(err.path(), Some(path));
+    assert_eq!(ObjPoolOptions::new().open(path).err().unwrap().kind(), io::ErrorKind::NotFound);
+
+    let err = ObjPool::options().create(true).layout("my\0layout").size(10 * 1024 * 1024).open(path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(!path.exists());
+}
+
+#[test]
+fn options_without_size() {
+    let path = Path::new("/tmp/test-options_without_size.pmemobj");
+    fs::write(path, vec![0; 1024 * 1024]).unwrap();
+    let err = ObjPool::options().create_new(true).open(path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.operation(), "pmemobj_create");
+    assert_eq!(ObjPool::options().create(true).open(path).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    // left as it was
+    assert_eq!(fs::read(path).unwrap(), vec![0; 1024 * 1024]);
+    fs::remove_file(path).unwrap();
+}
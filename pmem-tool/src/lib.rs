@@ -0,0 +1,14 @@
+//! Tooling over pools of every type
+//!
+//! `detect()` tells the type of a pool from its header and `AnyPool` opens it with the right library,
+//! for tools and scripts handed a file they know nothing about.
+//! The `pmem-tool` binary creates, inspects, checks and dumps pools from the command line.
+
+extern crate pmem;
+extern crate pmem_blk;
+extern crate pmem_log;
+extern crate pmem_obj;
+
+pub mod pool;
+
+pub use pool::{AnyPool, PoolInfo, PoolKind, detect};
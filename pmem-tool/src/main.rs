@@ -0,0 +1,328 @@
+//! Creates, inspects, checks and dumps persistent memory pools
+//!
+//! ```text
+//! pmem-tool [--json] create <obj|blk|log|raw> <path> --size <size> [--block-size <size>] [--layout <name>]
+//! pmem-tool [--json] info <path> [--type <type>]
+//! pmem-tool [--json] check <path> [--type <type>] [--layout <name>]
+//! pmem-tool [--json] dump <path> [--type <type>] [--offset <n>] [--length <n>] [--records]
+//! pmem-tool [--json] rm <path> [--force]
+//! ```
+//!
+//! The type of a pool is told by the signature of its header, see `pmem_tool::detect()`,
+//! `--type` overrides it.
+//! Files without a known signature are `raw` files, mapped with `pmem::pmap::PersistentMap`.
+//! Sizes accept a `K`, `M` or `G` suffix.
+//!
+//! `dump` prints a range of bytes of a `raw` file or of the data of a `log`, or the blocks of a `blk` pool.
+//! For `blk` pools `--offset` and `--length` count blocks, for a `log` with `--records` they count
+//! the records of a `pmem_log::RecordLog`.
+//!
+//! `check` exits with status 1 when the pool is not consistent.
+//! `rm` refuses to remove a `raw` file without `--force`.
+
+extern crate pmem;
+extern crate pmem_blk;
+extern crate pmem_log;
+extern crate pmem_obj;
+extern crate pmem_tool;
+
+mod report;
+
+use ::std::env;
+use ::std::ffi::{OsStr, OsString};
+use ::std::fs;
+use ::std::io;
+use ::std::path::Path;
+use ::std::process;
+
+use ::pmem::pmap::PersistentMap;
+use ::pmem_blk::BlkPool;
+use ::pmem_log::{Log, RecordLog};
+use ::pmem_obj::ObjPool;
+use ::pmem_tool::{AnyPool, PoolKind};
+
+use report::{Report, hex, hexdump, print_json_array};
+
+const USAGE: &str = "\
+Usage: pmem-tool [--json] create <obj|blk|log|raw> <path> --size <size> [--block-size <size>] [--layout <name>]
+       pmem-tool [--json] info <path> [--type <type>]
+       pmem-tool [--json] check <path> [--type <type>] [--layout <name>]
+       pmem-tool [--json] dump <path> [--type <type>] [--offset <n>] [--length <n>] [--records]
+       pmem-tool [--json] rm <path> [--force]";
+
+/// Block size of the `blk` pools created without `--block-size`
+const DEFAULT_BLOCK_SIZE: usize = 512;
+
+fn invalid(msg: String) -> io::Error { io::Error::new(io::ErrorKind::InvalidInput, msg) }
+
+/// Parses a size, with an optional `K`, `M` or `G` suffix
+fn parse_size(s: &str) -> io::Result<u64> {
+    let (digits, shift) = match s.chars().last() {
+        Some('K') | Some('k') => (&s[..s.len() - 1], 10),
+        Some('M') | Some('m') => (&s[..s.len() - 1], 20),
+        Some('G') | Some('g') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    digits.parse::<u64>()
+          .ok()
+          .and_then(|n| n.checked_mul(1 << shift))
+          .ok_or_else(|| invalid(format!("Invalid size {}", s)))
+}
+
+#[derive(Default)]
+struct Args {
+    json: bool,
+    kind: Option<PoolKind>,
+    size: Option<u64>,
+    block_size: Option<u64>,
+    layout: Option<String>,
+    offset: Option<u64>,
+    length: Option<u64>,
+    records: bool,
+    force: bool,
+    positional: Vec<OsString>,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = OsString>>(mut args: I) -> io::Result<Args> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            let name = arg.to_string_lossy();
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| invalid(format!("Missing value for {}", name)))?
+                    .into_string()
+                    .map_err(|v| invalid(format!("Invalid value {} for {}", v.to_string_lossy(), name)))
+            };
+            match arg.to_str() {
+                Some("--json") => parsed.json = true,
+                Some("--records") => parsed.records = true,
+                Some("--force") => parsed.force = true,
+                Some("--type") => parsed.kind = Some(value()?.parse()?),
+                Some("--size") => parsed.size = Some(parse_size(&value()?)?),
+                Some("--block-size") => parsed.block_size = Some(parse_size(&value()?)?),
+                Some("--layout") => parsed.layout = Some(value()?),
+                Some("--offset") => parsed.offset = Some(parse_size(&value()?)?),
+                Some("--length") => parsed.length = Some(parse_size(&value()?)?),
+                _ if name.starts_with("--") => return Err(invalid(format!("Unknown option {}", name))),
+                _ => parsed.positional.push(arg),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// The type given with `--type`, or the one detected for `path`
+    fn kind(&self, path: &Path) -> io::Result<PoolKind> {
+        match self.kind {
+            Some(kind) => Ok(kind),
+            None => pmem_tool::detect(path).map(|info| info.kind),
+        }
+    }
+
+    fn print(&self, report: &Report) {
+        if self.json {
+            println!("{}", report.to_json());
+        } else {
+            print!("{}", report.to_text());
+        }
+    }
+}
+
+fn create(args: &Args, kind: &OsStr, path: &Path) -> io::Result<()> {
+    let kind: PoolKind = kind.to_string_lossy().parse()?;
+    let size = args.size.ok_or_else(|| invalid("Missing --size".to_string()))? as usize;
+    match kind {
+        PoolKind::Obj => drop(ObjPool::create(path, args.layout.clone().unwrap_or_default(), size)?),
+        PoolKind::Blk => {
+            let block_size = args.block_size.map_or(DEFAULT_BLOCK_SIZE, |b| b as usize);
+            drop(BlkPool::create(path, block_size, size)?)
+        }
+        PoolKind::Log => drop(Log::create(path, size)?),
+        PoolKind::Raw => drop(PersistentMap::create(path, size, false, 0o666)?),
+    }
+    args.print(&Report::new().field("path", path).field("type", kind.name()).field("size", size));
+    Ok(())
+}
+
+/// Formats a UUID the usual way, `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`
+fn format_uuid(uuid: &[u8; 16]) -> String {
+    format!("{}-{}-{}-{}-{}",
+            hex(&uuid[..4]),
+            hex(&uuid[4..6]),
+            hex(&uuid[6..8]),
+            hex(&uuid[8..10]),
+            hex(&uuid[10..]))
+}
+
+fn info(args: &Args, path: &Path) -> io::Result<()> {
+    let header = pmem_tool::detect(path)?;
+    let kind = args.kind.unwrap_or(header.kind);
+    let mut report = Report::new().field("path", path).field("type", kind.name()).field("size", header.size);
+    if kind == header.kind && kind != PoolKind::Raw {
+        report = report.field("major", header.major as u64)
+                       .field("uuid", format_uuid(&header.uuid))
+                       .field("created", header.created);
+    }
+    // opening validates the pool beyond its header
+    match AnyPool::open_as(path, kind)? {
+        AnyPool::Obj(_) => {
+            if let Some(layout) = header.layout {
+                report = report.field("layout", layout);
+            }
+        }
+        AnyPool::Blk(pool) => {
+            report = report.field("block_size", pool.block_size()).field("capacity", pool.capacity());
+        }
+        AnyPool::Log(log) => report = report.field("capacity", log.capacity()).field("used", log.len()),
+        AnyPool::Raw(map) => report = report.field("is_pmem", map.is_pmem()),
+    }
+    args.print(&report);
+    Ok(())
+}
+
+/// Returns whether the pool is consistent
+fn check(args: &Args, path: &Path) -> io::Result<bool> {
+    let kind = args.kind(path)?;
+    let consistent = match kind {
+        PoolKind::Obj => ObjPool::check(path, args.layout.as_deref())?,
+        PoolKind::Blk => BlkPool::check(path, 0)?,
+        PoolKind::Log => Log::check(path)?,
+        PoolKind::Raw => {
+            return Err(invalid(format!("{} is a raw file, there is no metadata to check", path.display())))
+        }
+    };
+    args.print(&Report::new().field("path", path).field("type", kind.name()).field("consistent", consistent));
+    Ok(consistent)
+}
+
+/// Clamps the range given by `--offset` and `--length` to `len` items
+fn range(args: &Args, len: u64) -> (u64, u64) {
+    let start = args.offset.unwrap_or(0).min(len);
+    let end = args.length.map_or(len, |l| start.saturating_add(l).min(len));
+    (start, end)
+}
+
+fn dump_bytes(args: &Args, data: &[u8], offset: u64) {
+    if args.json {
+        args.print(&Report::new()
+            .field("offset", offset)
+            .field("length", data.len())
+            .field("data", hex(data)));
+    } else {
+        print!("{}", hexdump(data, offset));
+    }
+}
+
+fn dump(args: &Args, path: &Path) -> io::Result<()> {
+    match args.kind(path)? {
+        PoolKind::Obj => {
+            Err(invalid(format!("Dumping obj pools is not supported, {} is one", path.display())))
+        }
+        PoolKind::Raw => {
+            let map = PersistentMap::open(path)?;
+            let (start, end) = range(args, map.len() as u64);
+            dump_bytes(args, &map[start as usize..end as usize], start);
+            Ok(())
+        }
+        PoolKind::Blk => {
+            let pool = BlkPool::open_no_size(path)?;
+            let (start, end) = range(args, pool.capacity() as u64);
+            let mut block = vec![0; pool.block_size()];
+            let mut reports = Vec::new();
+            for blockno in start..end {
+                pool.read(&mut block, blockno as i64)?;
+                if args.json {
+                    reports.push(Report::new().field("block", blockno).field("data", hex(&block)));
+                } else {
+                    println!("block {}:", blockno);
+                    print!("{}", hexdump(&block, blockno * block.len() as u64));
+                }
+            }
+            if args.json {
+                print_json_array(&reports);
+            }
+            Ok(())
+        }
+        PoolKind::Log if args.records => {
+            let log = RecordLog::open(path)?;
+            let (start, end) = range(args, u64::MAX);
+            let mut records = log.records();
+            let mut reports = Vec::new();
+            let mut n = 0;
+            while n < end {
+                let offset = records.offset() as u64;
+                let record = match records.next() {
+                    Some(record) => record?,
+                    None => break,
+                };
+                if n >= start {
+                    if args.json {
+                        reports.push(Report::new()
+                            .field("record", n)
+                            .field("offset", offset)
+                            .field("length", record.len())
+                            .field("data", hex(&record)));
+                    } else {
+                        println!("record {} at offset {}, {} bytes:", n, offset, record.len());
+                        print!("{}", hexdump(&record, 0));
+                    }
+                }
+                n += 1;
+            }
+            if args.json {
+                print_json_array(&reports);
+            }
+            Ok(())
+        }
+        PoolKind::Log => {
+            let log = Log::open(path)?;
+            let (start, end) = range(args, log.len() as u64);
+            let mut data = vec![0; (end - start) as usize];
+            let n = log.read_at(start as usize, &mut data)?;
+            dump_bytes(args, &data[..n], start);
+            Ok(())
+        }
+    }
+}
+
+fn rm(args: &Args, path: &Path) -> io::Result<()> {
+    let kind = args.kind(path)?;
+    if kind == PoolKind::Raw && !args.force {
+        return Err(invalid(format!("{} is not a pool, use --force to remove it anyway", path.display())));
+    }
+    fs::remove_file(path)?;
+    args.print(&Report::new().field("path", path).field("type", kind.name()).field("removed", true));
+    Ok(())
+}
+
+fn run(args: &Args) -> io::Result<i32> {
+    let (command, rest) = match args.positional.split_first() {
+        Some((command, rest)) => (command.to_str(), rest),
+        None => (None, &[][..]),
+    };
+    match (command, rest) {
+        (Some("create"), [kind, path]) => create(args, kind, Path::new(path))?,
+        (Some("info"), [path]) => info(args, Path::new(path))?,
+        (Some("check"), [path]) => {
+            return check(args, Path::new(path)).map(|consistent| if consistent { 0 } else { 1 })
+        }
+        (Some("dump"), [path]) => dump(args, Path::new(path))?,
+        (Some("rm"), [path]) => rm(args, Path::new(path))?,
+        _ => {
+            eprintln!("{}", USAGE);
+            return Ok(2);
+        }
+    }
+    Ok(0)
+}
+
+fn main() {
+    let code = match Args::parse(env::args_os().skip(1)).and_then(|args| run(&args)) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("pmem-tool: {}", err);
+            1
+        }
+    };
+    process::exit(code);
+}
@@ -0,0 +1,202 @@
+//! Pools of any type
+//!
+//! Every pool created by **libpmemobj**, **libpmemblk** or **libpmemlog** starts with a 4096 byte header
+//! whose signature tells the library it belongs to, followed by the metadata of that library.
+//! `detect()` reads them without opening the pool, `AnyPool::open()` opens the pool with the right library.
+//! Files without a known signature are raw files, opened as a `PersistentMap`.
+//!
+//! All the header fields are little-endian.
+
+use ::std::fmt;
+use ::std::fs::File;
+use ::std::io::{self, Read};
+use ::std::path::Path;
+use ::std::str::FromStr;
+
+use ::pmem::pmap::PersistentMap;
+use ::pmem_blk::BlkPool;
+use ::pmem_log::Log;
+use ::pmem_obj::ObjPool;
+
+/// Size of the header every pool starts with
+pub const POOL_HDR_SIZE: usize = 4096;
+
+/// Longest layout name of an `obj` pool, terminating NUL included
+pub const MAX_LAYOUT: usize = 1024;
+
+const MAJOR_OFFSET: usize = 8;
+const UUID_OFFSET: usize = 40;
+const CRTIME_OFFSET: usize = 120;
+
+/// Type of a pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PoolKind {
+    Obj,
+    Blk,
+    Log,
+    Raw,
+}
+
+impl PoolKind {
+    /// The short name of the type: `obj`, `blk`, `log` or `raw`
+    pub fn name(self) -> &'static str {
+        match self {
+            PoolKind::Obj => "obj",
+            PoolKind::Blk => "blk",
+            PoolKind::Log => "log",
+            PoolKind::Raw => "raw",
+        }
+    }
+
+    fn from_signature(signature: &[u8]) -> PoolKind {
+        match signature {
+            b"PMEMOBJ\0" => PoolKind::Obj,
+            b"PMEMBLK\0" => PoolKind::Blk,
+            b"PMEMLOG\0" => PoolKind::Log,
+            _ => PoolKind::Raw,
+        }
+    }
+}
+
+impl fmt::Display for PoolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.name()) }
+}
+
+impl FromStr for PoolKind {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, io::Error> {
+        match s {
+            "obj" => Ok(PoolKind::Obj),
+            "blk" => Ok(PoolKind::Blk),
+            "log" => Ok(PoolKind::Log),
+            "raw" => Ok(PoolKind::Raw),
+            _ => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                   format!("Unknown pool type {}, expected obj, blk, log or raw", s)))
+            }
+        }
+    }
+}
+
+/// What the header of a pool tells, see `detect()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolInfo {
+    pub kind: PoolKind,
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Major version of the on-media format, 0 for a raw file
+    pub major: u32,
+    /// Unique identifier of the pool, all zeroes for a raw file
+    pub uuid: [u8; 16],
+    /// Creation time of the pool in seconds since the Unix epoch, 0 for a raw file
+    pub created: u64,
+    /// Layout name of an `obj` pool
+    pub layout: Option<String>,
+    /// Block size of a `blk` pool
+    pub block_size: Option<usize>,
+    /// Capacity in bytes of a `log` pool
+    pub capacity: Option<u64>,
+    /// Bytes in use in a `log` pool
+    pub used: Option<u64>,
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&buf[at..at + 4]);
+    u32::from_le_bytes(b)
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&buf[at..at + 8]);
+    u64::from_le_bytes(b)
+}
+
+/// Reads the header of the file at `path` and tells the type of the pool, without opening it
+///
+/// Files too short to hold a header, or without a known signature, are `PoolKind::Raw`.
+/// Nothing is validated beyond the signature, `AnyPool::open()` or a `check()` of the pool does that.
+pub fn detect<P: AsRef<Path>>(path: P) -> Result<PoolInfo, io::Error> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut header = Vec::with_capacity(POOL_HDR_SIZE + MAX_LAYOUT);
+    file.take((POOL_HDR_SIZE + MAX_LAYOUT) as u64).read_to_end(&mut header)?;
+
+    let mut info = PoolInfo {
+        kind: PoolKind::Raw,
+        size,
+        major: 0,
+        uuid: [0; 16],
+        created: 0,
+        layout: None,
+        block_size: None,
+        capacity: None,
+        used: None,
+    };
+    if header.len() < POOL_HDR_SIZE + 24 {
+        return Ok(info);
+    }
+    info.kind = PoolKind::from_signature(&header[..8]);
+    if info.kind == PoolKind::Raw {
+        return Ok(info);
+    }
+    info.major = u32_at(&header, MAJOR_OFFSET);
+    info.uuid.copy_from_slice(&header[UUID_OFFSET..UUID_OFFSET + 16]);
+    info.created = u64_at(&header, CRTIME_OFFSET);
+
+    let meta = &header[POOL_HDR_SIZE..];
+    match info.kind {
+        PoolKind::Obj => {
+            let len = meta.iter().position(|&b| b == 0).unwrap_or(meta.len());
+            info.layout = Some(String::from_utf8_lossy(&meta[..len]).into_owned());
+        }
+        PoolKind::Blk => info.block_size = Some(u32_at(meta, 0) as usize),
+        PoolKind::Log => {
+            let (start, end, write) = (u64_at(meta, 0), u64_at(meta, 8), u64_at(meta, 16));
+            info.capacity = Some(end.saturating_sub(start));
+            info.used = Some(write.saturating_sub(start));
+        }
+        PoolKind::Raw => {}
+    }
+    Ok(info)
+}
+
+/// A pool of any type, opened with the library it belongs to
+pub enum AnyPool {
+    Obj(ObjPool),
+    Blk(BlkPool),
+    Log(Log),
+    Raw(PersistentMap),
+}
+
+impl AnyPool {
+    /// Opens the pool at `path`, whatever its type
+    ///
+    /// The type is told by the signature of the header, see `detect()`.
+    /// An `obj` pool is opened whatever its layout, a `blk` pool whatever its block size.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AnyPool, io::Error> {
+        let kind = detect(path.as_ref())?.kind;
+        AnyPool::open_as(path, kind)
+    }
+
+    /// Opens the pool at `path` as a pool of type `kind`
+    pub fn open_as<P: AsRef<Path>>(path: P, kind: PoolKind) -> Result<AnyPool, io::Error> {
+        Ok(match kind {
+            PoolKind::Obj => AnyPool::Obj(ObjPool::open_no_layout(path)?),
+            PoolKind::Blk => AnyPool::Blk(BlkPool::open_no_size(path)?),
+            PoolKind::Log => AnyPool::Log(Log::open(path)?),
+            PoolKind::Raw => AnyPool::Raw(PersistentMap::open(path)?),
+        })
+    }
+
+    /// The type of the pool
+    pub fn kind(&self) -> PoolKind {
+        match *self {
+            AnyPool::Obj(_) => PoolKind::Obj,
+            AnyPool::Blk(_) => PoolKind::Blk,
+            AnyPool::Log(_) => PoolKind::Log,
+            AnyPool::Raw(_) => PoolKind::Raw,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Output of the commands, as text for humans or as JSON for scripts
+
+use ::std::fmt::{self, Write};
+use ::std::path::Path;
+
+/// A value reported by a command
+pub enum Value {
+    Str(String),
+    Num(u64),
+    Bool(bool),
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self { Value::Str(s) }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Self { Value::Str(s.to_string()) }
+}
+
+impl<'a> From<&'a Path> for Value {
+    fn from(p: &'a Path) -> Self { Value::Str(p.display().to_string()) }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self { Value::Num(n) }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Self { Value::Num(n as u64) }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self { Value::Bool(b) }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Value::Str(ref s) => f.write_str(s),
+            Value::Num(n) => n.fmt(f),
+            Value::Bool(b) => b.fmt(f),
+        }
+    }
+}
+
+/// Ordered set of named values, printed as `name: value` lines or as a JSON object
+#[derive(Default)]
+pub struct Report {
+    fields: Vec<(&'static str, Value)>,
+}
+
+impl Report {
+    pub fn new() -> Self { Report::default() }
+
+    pub fn field<V: Into<Value>>(mut self, name: &'static str, value: V) -> Self {
+        self.fields.push((name, value.into()));
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{");
+        for (i, &(name, ref value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            push_json_str(&mut out, name);
+            out.push_str(": ");
+            match *value {
+                Value::Str(ref s) => push_json_str(&mut out, s),
+                ref value => {
+                    let _ = write!(out, "{}", value);
+                }
+            }
+        }
+        out.push('}');
+        out
+    }
+
+    pub fn to_text(&self) -> String {
+        let width = self.fields.iter().map(|&(name, _)| name.len()).max().unwrap_or(0);
+        let mut out = String::new();
+        for &(name, ref value) in &self.fields {
+            let _ = writeln!(out, "{:<width$}  {}", name, value, width = width + 1);
+        }
+        out
+    }
+}
+
+/// Prints `reports` as a JSON array
+pub fn print_json_array(reports: &[Report]) {
+    let items: Vec<String> = reports.iter().map(Report::to_json).collect();
+    println!("[{}]", items.join(",\n "));
+}
+
+fn push_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Lowercase hexadecimal encoding of `data`
+pub fn hex(data: &[u8]) -> String {
+    let mut out = String::with_capacity(2 * data.len());
+    for b in data {
+        let _ = write!(out, "{:02x}", b);
+    }
+    out
+}
+
+/// Classic hexdump of `data`, 16 bytes per line, the offsets starting at `base`
+pub fn hexdump(data: &[u8], base: u64) -> String {
+    let mut out = String::new();
+    for (i, line) in data.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x} ", base + 16 * i as u64);
+        for j in 0..16 {
+            match line.get(j) {
+                Some(b) => {
+                    let _ = write!(out, " {:02x}", b);
+                }
+                None => out.push_str("   "),
+            }
+            if j == 7 {
+                out.push(' ');
+            }
+        }
+        out.push_str("  |");
+        out.extend(line.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+        out.push_str("|\n");
+    }
+    out
+}
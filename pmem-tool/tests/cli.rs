@@ -0,0 +1,106 @@
+mod common;
+
+use ::std::ffi::OsStr;
+use ::std::fs;
+use ::std::os::unix::ffi::OsStrExt;
+use ::std::path::Path;
+use ::std::process::{Command, Output};
+
+use common::clean;
+
+fn pmem_tool<S: AsRef<OsStr>>(args: &[S]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pmem-tool")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String { String::from_utf8(output.stdout.clone()).unwrap() }
+
+#[test]
+fn log_info_json() {
+    let path = "/tmp/test-tool-log_info_json.pmemlog";
+    clean(path);
+    assert!(pmem_tool(&["create", "log", path, "--size", "2M"]).status.success());
+
+    let output = pmem_tool(&["--json", "info", path]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.starts_with(&format!("{{\"path\": \"{}\", \"type\": \"log\", \"size\": 2097152, ", path)));
+    assert!(out.contains("\"used\": 0}"));
+
+    let output = pmem_tool(&["--json", "check", path]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("\"consistent\": true"));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn non_utf8_path() {
+    let path = OsStr::from_bytes(b"/tmp/test-tool-non_utf8_path-\xff.pmemlog");
+    clean(path);
+    let create = [OsStr::new("create"), OsStr::new("log"), path, OsStr::new("--size"), OsStr::new("2M")];
+    assert!(pmem_tool(&create).status.success());
+
+    let output = pmem_tool(&[OsStr::new("info"), path]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("type         log\n"));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn blk_info() {
+    let path = "/tmp/test-tool-blk_info.pmemblk";
+    clean(path);
+    assert!(pmem_tool(&["create", "blk", path, "--size", "32M", "--block-size", "1K"]).status.success());
+
+    let output = pmem_tool(&["info", path]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("type         blk\n"));
+    assert!(out.contains("block_size   1024\n"));
+
+    let output = pmem_tool(&["--json", "dump", path, "--offset", "3", "--length", "2"]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.starts_with("[{\"block\": 3, \"data\": \"0000"));
+    assert!(out.contains("{\"block\": 4, "));
+    assert!(!out.contains("{\"block\": 5, "));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn raw_dump() {
+    let path = "/tmp/test-tool-raw_dump.bin";
+    clean(path);
+    let mut data = vec![0; 4096];
+    data[16..21].copy_from_slice(b"hello");
+    fs::write(path, &data).unwrap();
+
+    let output = pmem_tool(&["dump", path, "--offset", "16", "--length", "5"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output),
+               "00000010  68 65 6c 6c 6f                                    |hello|\n");
+
+    let output = pmem_tool(&["--json", "dump", path, "--offset", "16", "--length", "5"]);
+    assert_eq!(stdout(&output), "{\"offset\": 16, \"length\": 5, \"data\": \"68656c6c6f\"}\n");
+
+    let output = pmem_tool(&["check", path]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn rm() {
+    let path = "/tmp/test-tool-rm.bin";
+    clean(path);
+    fs::write(path, vec![0; 4096]).unwrap();
+
+    assert!(!pmem_tool(&["rm", path]).status.success());
+    assert!(Path::new(path).exists());
+
+    assert!(pmem_tool(&["rm", path, "--force"]).status.success());
+    assert!(!Path::new(path).exists());
+}
+
+#[test]
+fn usage() {
+    assert_eq!(pmem_tool(&["frobnicate"]).status.code(), Some(2));
+    assert_eq!(pmem_tool(&["info", "x", "--bogus"]).status.code(), Some(1));
+}
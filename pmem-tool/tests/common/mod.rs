@@ -0,0 +1,12 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use ::std::fs;
+use ::std::path::Path;
+
+/// Removes what a previous run left at `path`
+pub fn clean<P: AsRef<Path>>(path: P) {
+    if path.as_ref().exists() {
+        fs::remove_file(path).unwrap();
+    }
+}
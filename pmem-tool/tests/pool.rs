@@ -0,0 +1,97 @@
+extern crate pmem_blk;
+extern crate pmem_log;
+extern crate pmem_obj;
+extern crate pmem_tool;
+
+mod common;
+
+use ::std::fs;
+use ::std::path::Path;
+
+use ::pmem_blk::BlkPool;
+use ::pmem_log::Log;
+use ::pmem_obj::ObjPool;
+use ::pmem_tool::{AnyPool, PoolKind, detect};
+
+use common::clean;
+
+#[test]
+fn detect_log() {
+    let path = Path::new("/tmp/test-pool-detect_log.pmemlog");
+    clean(path);
+    let mut log = Log::create(path, 2 * 1024 * 1024).unwrap();
+    log.append(b"hello").unwrap();
+    let capacity = log.capacity() as u64;
+    drop(log);
+
+    let info = detect(path).unwrap();
+    assert_eq!(info.kind, PoolKind::Log);
+    assert_eq!(info.size, 2 * 1024 * 1024);
+    assert_eq!(info.capacity, Some(capacity));
+    assert_eq!(info.used, Some(5));
+    assert!(info.major > 0);
+    assert!(info.created > 0);
+
+    match AnyPool::open(path).unwrap() {
+        AnyPool::Log(log) => assert_eq!(log.len(), 5),
+        pool => panic!("Opened a {} pool", pool.kind()),
+    }
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn detect_blk() {
+    let path = Path::new("/tmp/test-pool-detect_blk.pmemblk");
+    clean(path);
+    drop(BlkPool::create(path, 1024, 32 * 1024 * 1024).unwrap());
+
+    let info = detect(path).unwrap();
+    assert_eq!(info.kind, PoolKind::Blk);
+    assert_eq!(info.block_size, Some(1024));
+    assert_eq!(info.layout, None);
+
+    match AnyPool::open(path).unwrap() {
+        AnyPool::Blk(pool) => assert_eq!(pool.block_size(), 1024),
+        pool => panic!("Opened a {} pool", pool.kind()),
+    }
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn detect_obj() {
+    let path = Path::new("/tmp/test-pool-detect_obj.pmemobj");
+    clean(path);
+    drop(ObjPool::create(path, "my_layout", 10 * 1024 * 1024).unwrap());
+
+    let info = detect(path).unwrap();
+    assert_eq!(info.kind, PoolKind::Obj);
+    assert_eq!(info.layout, Some("my_layout".to_string()));
+    assert_eq!(AnyPool::open(path).unwrap().kind(), PoolKind::Obj);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn detect_raw() {
+    let path = Path::new("/tmp/test-pool-detect_raw.bin");
+    clean(path);
+    fs::write(path, vec![7; 8192]).unwrap();
+
+    let info = detect(path).unwrap();
+    assert_eq!(info.kind, PoolKind::Raw);
+    assert_eq!(info.size, 8192);
+    assert_eq!(info.major, 0);
+    assert_eq!(AnyPool::open(path).unwrap().kind(), PoolKind::Raw);
+
+    // too short to hold a header
+    fs::write(path, b"PMEMLOG\0").unwrap();
+    assert_eq!(detect(path).unwrap().kind, PoolKind::Raw);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn kind_names() {
+    for &kind in &[PoolKind::Obj, PoolKind::Blk, PoolKind::Log, PoolKind::Raw] {
+        assert_eq!(kind.name().parse::<PoolKind>().unwrap(), kind);
+    }
+    assert!("nope".parse::<PoolKind>().is_err());
+}
@@ -0,0 +1,34 @@
+//! CRC-32 (IEEE 802.3) checksums
+//!
+//! Used by the pools built on top of this crate to detect torn writes and media corruption.
+
+/// Lookup table
+const TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+/// Computes the CRC-32 (IEEE 802.3) of `buf`
+pub fn crc32(buf: &[u8]) -> u32 { crc32_parts(&[buf]) }
+
+/// Computes the CRC-32 (IEEE 802.3) of the concatenation of `parts`
+pub fn crc32_parts(parts: &[&[u8]]) -> u32 {
+    let mut c = !0u32;
+    for b in parts.iter().flat_map(|p| p.iter()) {
+        c = TABLE[((c ^ *b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
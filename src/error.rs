@@ -0,0 +1,115 @@
+//! Errors of the NVM Library calls
+//!
+//! The libraries report a failure through `errno` and a thread-local error message,
+//! both only meaningful right after the failing call.
+//! An `Error` captures them at once, with the name of the call and the path of the pool it was given,
+//! and converts into an `io::Error` that still holds it:
+//!
+//! ```no_run
+//! # use std::io;
+//! # use pmem::pmap::PersistentMap;
+//! fn open() -> Result<PersistentMap, io::Error> {
+//!     let map = PersistentMap::open("/mnt/pmem/data")?;
+//!     Ok(map)
+//! }
+//!
+//! if let Err(err) = open() {
+//!     if let Some(err) = pmem::Error::downcast_ref(&err) {
+//!         println!("{} failed with errno {:?}", err.operation(), err.raw_os_error());
+//!     }
+//! }
+//! ```
+
+use ::std::error;
+use ::std::ffi::CString;
+use ::std::fmt;
+use ::std::io;
+use ::std::os::unix::ffi::OsStrExt;
+use ::std::path::{Path, PathBuf};
+
+/// Failure of a call to one of the NVM libraries
+#[derive(Debug, Clone)]
+pub struct Error {
+    operation: &'static str,
+    path: Option<PathBuf>,
+    errno: Option<i32>,
+    message: Option<String>,
+    kind: io::ErrorKind,
+}
+
+impl Error {
+    /// Captures `errno` after the call `operation` failed
+    ///
+    /// Must be called right after the failing call, before anything else can touch `errno`.
+    /// The message of the library goes in with `with_message()`.
+    pub fn last_os_error(operation: &'static str) -> Error {
+        let err = io::Error::last_os_error();
+        Error { operation, path: None, errno: err.raw_os_error(), message: None, kind: err.kind() }
+    }
+
+    /// An error not reported through `errno`, like an unexpected return value
+    pub fn new<S: Into<String>>(kind: io::ErrorKind, operation: &'static str, message: S) -> Error {
+        Error { operation, path: None, errno: None, message: Some(message.into()), kind }
+    }
+
+    /// Sets the path the call was given
+    pub fn with_path<P: AsRef<Path>>(mut self, path: P) -> Error {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the error message of the library, as returned by its `errormsg()`
+    pub fn with_message(mut self, message: Option<String>) -> Error {
+        self.message = message.filter(|m| !m.is_empty());
+        self
+    }
+
+    /// The name of the failed call, like `pmemblk_open`
+    pub fn operation(&self) -> &'static str { self.operation }
+
+    /// The path the call was given, if any
+    pub fn path(&self) -> Option<&Path> { self.path.as_deref() }
+
+    /// The `errno` set by the call, if any
+    pub fn raw_os_error(&self) -> Option<i32> { self.errno }
+
+    /// The error message of the library, if any
+    pub fn message(&self) -> Option<&str> { self.message.as_deref() }
+
+    /// The kind of `io::Error` this error converts into
+    pub fn kind(&self) -> io::ErrorKind { self.kind }
+
+    /// The `Error` an `io::Error` was converted from, if any
+    pub fn downcast_ref(err: &io::Error) -> Option<&Error> { err.get_ref()?.downcast_ref() }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.operation)?;
+        if let Some(ref path) = self.path {
+            write!(f, "({})", path.display())?;
+        }
+        match (self.message.as_ref(), self.errno) {
+            (Some(message), Some(errno)) => write!(f, ": {} ({})", message, io::Error::from_raw_os_error(errno)),
+            (Some(message), None) => write!(f, ": {}", message),
+            (None, Some(errno)) => write!(f, ": {}", io::Error::from_raw_os_error(errno)),
+            (None, None) => write!(f, ": {}", io::Error::from(self.kind)),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error { io::Error::new(err.kind, err) }
+}
+
+/// Converts `path` to pass it to the call `operation`
+///
+/// The bytes of the path are passed as they are, they do not need to be valid UTF-8.
+/// A path holding a NUL byte cannot be passed to C, it fails with `ErrorKind::InvalidInput`.
+pub fn path_to_cstring(operation: &'static str, path: &Path) -> Result<CString, Error> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+        Error::new(io::ErrorKind::InvalidInput, operation, "The path contains a NUL byte").with_path(path)
+    })
+}
@@ -20,6 +20,8 @@ extern crate libc;
 
 // Modules
 
+pub mod crc;
+pub mod error;
 pub mod pmap;
 pub mod ptr;
 pub mod cell;
@@ -28,13 +30,14 @@ pub mod nodrain;
 // lib module
 
 use ::std::mem;
-use ::std::io;
 use ::std::ffi::CStr;
 
 use ::libc::{c_void, c_uint};
 use ::libc::size_t;
 use ::pmem_sys as ffi;
 
+pub use error::Error;
+
 /// Description of the last error
 ///
 /// The error message is thread-local; errors encountered in one thread do not affect its value in other threads.
@@ -120,21 +123,21 @@ pub fn persist<T>(x: &T) {
 ///     }
 /// }
 /// ```
-pub fn msync<T>(x: &T) -> Result<(), io::Error> {
+pub fn msync<T>(x: &T) -> Result<(), Error> {
     let len = mem::size_of::<T>();
     let r = unsafe { ffi::pmem_msync(x as *const _ as *const c_void, len as size_t) };
     if r == -1 {
-        Err(io::Error::last_os_error())
+        Err(Error::last_os_error("pmem_msync").with_message(errormsg()))
     } else {
         Ok(())
     }
 }
 
-pub fn msync_unsized<T: ?Sized>(x: &T) -> Result<(), io::Error> {
+pub fn msync_unsized<T: ?Sized>(x: &T) -> Result<(), Error> {
     let len = mem::size_of_val(x);
     let r = unsafe { ffi::pmem_msync(x as *const _ as *const c_void, len as size_t) };
     if r == -1 {
-        Err(io::Error::last_os_error())
+        Err(Error::last_os_error("pmem_msync").with_message(errormsg()))
     } else {
         Ok(())
     }
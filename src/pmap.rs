@@ -1,14 +1,14 @@
 //! Persistent memory maps
 
-use ::std::mem;
 use ::std::io;
-use ::std::ffi::CString;
+use ::std::mem;
 use ::std::path::Path;
 
 use ::libc::{c_void, c_int};
 use ::libc::{size_t, mode_t};
 
 use pmem_sys as ffi;
+use error::{self, Error};
 use ptr::{self, PmemConstPtr, PmemMutPtr};
 use cell::PmemMutRef;
 
@@ -21,6 +21,9 @@ pub struct PersistentMap {
     len: usize,
 }
 
+// The mapping is not tied to the thread that created it.
+unsafe impl Send for PersistentMap {}
+
 impl PersistentMap {
     /// Creates a new read/write mapping for the named file
     ///
@@ -34,13 +37,14 @@ impl PersistentMap {
     fn map_file<P: AsRef<Path>>(path: P,
                                 len: usize,
                                 flags: CreationFlags,
-                                mode: u16)
-                                -> Result<Self, io::Error> {
-        let path = CString::new(path.as_ref().to_str().unwrap()).unwrap();
+                                mode: u32)
+                                -> Result<Self, Error> {
+        let path = path.as_ref();
+        let cpath = error::path_to_cstring("pmem_map_file", path)?;
         let mut mapped_len = 0;
         let mut is_pmem = 0;
         let r = unsafe {
-            ffi::pmem_map_file(path.as_ptr(),
+            ffi::pmem_map_file(cpath.as_ptr(),
                                len as size_t,
                                flags.bits as c_int,
                                mode as mode_t,
@@ -48,14 +52,14 @@ impl PersistentMap {
                                &mut is_pmem as *mut _)
         };
         if r.is_null() {
-            Err(io::Error::last_os_error())
+            Err(Error::last_os_error("pmem_map_file").with_message(::errormsg()).with_path(path))
         } else {
             let is_pmem = is_pmem > 0;
             Ok(PersistentMap { is_pmem: is_pmem, buf: r, len: mapped_len })
         }
     }
 
-    pub fn create<P: AsRef<Path>>(path: P, len: usize, sparse: bool, mode: u16) -> Result<Self, io::Error> {
+    pub fn create<P: AsRef<Path>>(path: P, len: usize, sparse: bool, mode: u32) -> Result<Self, Error> {
         let mut flags = FILE_CREATE | FILE_EXCL;
         if sparse {
             flags = flags | FILE_SPARSE;
@@ -66,8 +70,8 @@ impl PersistentMap {
     pub fn create_tmp<D: AsRef<Path>>(dir: D,
                                       len: usize,
                                       sparse: bool,
-                                      mode: u16)
-                                      -> Result<Self, io::Error> {
+                                      mode: u32)
+                                      -> Result<Self, Error> {
         let mut flags = FILE_TMPFILE | FILE_EXCL;
         if sparse {
             flags = flags | FILE_SPARSE;
@@ -75,15 +79,15 @@ impl PersistentMap {
         PersistentMap::map_file(dir, len, flags, mode)
     }
 
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         PersistentMap::map_file(path, 0, CreationFlags::empty(), 0)
     }
 
     pub fn open_or_create<P: AsRef<Path>>(path: P,
                                           len: usize,
                                           sparse: bool,
-                                          mode: u16)
-                                          -> Result<Self, io::Error> {
+                                          mode: u32)
+                                          -> Result<Self, Error> {
         let mut flags = FILE_CREATE;
         if sparse {
             flags = flags | FILE_SPARSE;
@@ -91,6 +95,9 @@ impl PersistentMap {
         PersistentMap::map_file(path, len, flags, mode)
     }
 
+    /// Options to open or create a mapping, see `PersistentMapOptions`
+    pub fn options() -> PersistentMapOptions { PersistentMapOptions::new() }
+
     pub fn is_pmem(&self) -> bool { self.is_pmem }
 
     pub fn len(&self) -> usize { self.len }
@@ -153,6 +160,118 @@ impl Drop for PersistentMap {
     }
 }
 
+/// Options and flags to open or create a `PersistentMap`, in the style of `std::fs::OpenOptions`
+///
+/// ```no_run
+/// # use pmem::pmap::PersistentMapOptions;
+/// let mut options = PersistentMapOptions::new();
+/// options.create(true).len(64 * 1024 * 1024).sparse(true).mode(0o600);
+/// let map = options.open("/mnt/pmem/data").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct PersistentMapOptions {
+    len: usize,
+    mode: u32,
+    create: bool,
+    create_new: bool,
+    sparse: bool,
+    tmpfile: bool,
+}
+
+impl PersistentMapOptions {
+    /// Options to open an existing file, mapping all of it
+    pub fn new() -> Self {
+        PersistentMapOptions {
+            len: 0,
+            mode: 0o666,
+            create: false,
+            create_new: false,
+            sparse: false,
+            tmpfile: false,
+        }
+    }
+
+    /// The size of the file to create, required to create one
+    ///
+    /// Opening with `create()`, `create_new()` or `tmpfile()` and no `len` fails with `InvalidInput`.
+    pub fn len(&mut self, len: usize) -> &mut Self {
+        self.len = len;
+        self
+    }
+
+    /// The permissions of a created file, `0o666` by default, the process umask applies
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Creates the file if it does not exist, opens it otherwise
+    ///
+    /// An existing file is mapped as it is, `len()` only applies to a created one.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Creates the file, failing if it already exists
+    ///
+    /// Takes precedence over `create()`.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Creates a sparse (holey) file instead of allocating all its blocks
+    pub fn sparse(&mut self, sparse: bool) -> &mut Self {
+        self.sparse = sparse;
+        self
+    }
+
+    /// Maps an unnamed temporary file, created in the directory given to `open()`
+    ///
+    /// The file is gone once the mapping is dropped.
+    pub fn tmpfile(&mut self, tmpfile: bool) -> &mut Self {
+        self.tmpfile = tmpfile;
+        self
+    }
+
+    /// The flags passed to `pmem_map_file` to create the file
+    ///
+    /// Always with `FILE_EXCL` or `FILE_TMPFILE`, without them an existing file is resized to `len`.
+    fn create_flags(&self) -> CreationFlags {
+        let flags = if self.tmpfile { FILE_TMPFILE | FILE_CREATE } else { FILE_CREATE | FILE_EXCL };
+        if self.sparse { flags | FILE_SPARSE } else { flags }
+    }
+
+    /// Opens the file at `path` with these options, or a temporary file in the directory `path`
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<PersistentMap, Error> {
+        let path = path.as_ref();
+        if (self.tmpfile || self.create_new || self.create) && self.len == 0 {
+            let msg = "Creating a map needs a non-zero len";
+            return Err(Error::new(io::ErrorKind::InvalidInput, "pmem_map_file", msg).with_path(path));
+        }
+        if self.tmpfile || self.create_new {
+            return PersistentMap::map_file(path, self.len, self.create_flags(), self.mode);
+        }
+        match PersistentMap::map_file(path, 0, CreationFlags::empty(), 0) {
+            Err(ref err) if self.create && err.kind() == io::ErrorKind::NotFound => {
+                match PersistentMap::map_file(path, self.len, self.create_flags(), self.mode) {
+                    // created by someone else in the meantime
+                    Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                        PersistentMap::map_file(path, 0, CreationFlags::empty(), 0)
+                    }
+                    r => r,
+                }
+            }
+            r => r,
+        }
+    }
+}
+
+impl Default for PersistentMapOptions {
+    fn default() -> Self { PersistentMapOptions::new() }
+}
+
 bitflags! {
     flags CreationFlags: i32 {
 /// Create the named file if it does not exist
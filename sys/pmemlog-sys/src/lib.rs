@@ -34,4 +34,12 @@ extern "C" {
                                                      -> c_int,
                         // int (*process_chunk)(const void *buf, size_t len, void *arg),
                         arg: *mut c_void);
+
+    // Managing library behavior:
+
+    pub fn pmemlog_check(path: *const c_char) -> c_int;
+
+    // Error handling:
+
+    pub fn pmemlog_errormsg() -> *const c_char;
 }
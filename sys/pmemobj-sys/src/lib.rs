@@ -37,6 +37,10 @@ extern "C" {
     pub fn pmemobj_flush(pop: *mut PMEMobjpool, addr: *const c_void, len: size_t);
     pub fn pmemobj_drain(pop: *mut PMEMobjpool);
 
+    // Managing library behavior:
+
+    pub fn pmemobj_check(path: *const c_char, layout: *const c_char) -> c_int;
+
     // Error handling:
 
     pub fn pmemobj_errormsg() -> *const c_char;
@@ -0,0 +1,15 @@
+extern crate pmem;
+
+use pmem::crc::{crc32, crc32_parts};
+
+#[test]
+fn check_value() {
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn parts() {
+    assert_eq!(crc32_parts(&[b"1234", b"", b"56789"]), crc32(b"123456789"));
+    assert_eq!(crc32_parts(&[]), 0);
+}
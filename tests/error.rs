@@ -0,0 +1,37 @@
+extern crate pmem;
+
+use ::std::io;
+use ::std::path::Path;
+
+use pmem::pmap::PersistentMap;
+use pmem::Error;
+
+#[test]
+fn map_missing_file() {
+    let path = Path::new("/tmp/test-error-missing/map");
+    let err = match PersistentMap::open(path) {
+        Err(err) => err,
+        Ok(_) => panic!("Opened a missing file"),
+    };
+    assert_eq!(err.operation(), "pmem_map_file");
+    assert_eq!(err.path(), Some(path));
+    assert_eq!(err.raw_os_error(), Some(2));
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert!(err.to_string().starts_with("pmem_map_file(/tmp/test-error-missing/map): "));
+}
+
+#[test]
+fn into_io_error() {
+    let err = Error::new(io::ErrorKind::InvalidData, "pmem_check", "bad header").with_path("/tmp/pool");
+    assert_eq!(err.to_string(), "pmem_check(/tmp/pool): bad header");
+
+    let io_err: io::Error = err.into();
+    assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(io_err.to_string(), "pmem_check(/tmp/pool): bad header");
+    let err = Error::downcast_ref(&io_err).unwrap();
+    assert_eq!(err.operation(), "pmem_check");
+    assert_eq!(err.message(), Some("bad header"));
+    assert_eq!(err.raw_os_error(), None);
+
+    assert!(Error::downcast_ref(&io::Error::other("other")).is_none());
+}
@@ -0,0 +1,75 @@
+extern crate pmem;
+
+use ::std::fs;
+use ::std::io;
+use ::std::os::unix::fs::PermissionsExt;
+use ::std::path::Path;
+
+use pmem::pmap::{PersistentMap, PersistentMapOptions};
+
+#[test]
+fn create() {
+    let path = Path::new("/tmp/test-options-create.map");
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+    {
+        let mut p = PersistentMap::options().create(true).len(1024 * 1024).mode(0o600).open(path).unwrap();
+        assert_eq!(p.len(), 1024 * 1024);
+        p[1024 * 1024 - 1] = 42;
+    }
+    assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+
+    // opens the existing file as it is, whatever len
+    let p = PersistentMap::options().create(true).len(4096).open(path).unwrap();
+    assert_eq!(p.len(), 1024 * 1024);
+    assert_eq!(p[1024 * 1024 - 1], 42);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn create_new() {
+    let path = Path::new("/tmp/test-options-create_new.map");
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+    let mut options = PersistentMapOptions::new();
+    options.create_new(true).len(1024 * 1024).sparse(true);
+    {
+        let _p = options.open(path).unwrap();
+    }
+    let err = match options.open(path) {
+        Err(err) => err,
+        Ok(_) => panic!("Created a file that already exists"),
+    };
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn open_missing() {
+    let path = "/tmp/test-options-open_missing.map";
+    let err = match PersistentMapOptions::new().len(1024 * 1024).open(path) {
+        Err(err) => err,
+        Ok(_) => panic!("Created a file without create"),
+    };
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert!(!Path::new(path).exists());
+}
+
+#[test]
+fn create_empty() {
+    let path = "/tmp/test-options-create_empty.map";
+    let err = match PersistentMap::options().create(true).open(path) {
+        Err(err) => err,
+        Ok(_) => panic!("Created an empty map"),
+    };
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(!Path::new(path).exists());
+}
+
+#[test]
+fn tmpfile() {
+    let p = PersistentMap::options().tmpfile(true).len(1024 * 1024).open("/tmp").unwrap();
+    assert_eq!(p.len(), 1024 * 1024);
+}
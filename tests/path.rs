@@ -0,0 +1,34 @@
+extern crate pmem;
+
+use ::std::ffi::OsStr;
+use ::std::fs;
+use ::std::io;
+use ::std::os::unix::ffi::OsStrExt;
+use ::std::path::Path;
+
+use pmem::pmap::PersistentMap;
+
+#[test]
+fn non_utf8_path() {
+    let path = Path::new(OsStr::from_bytes(b"/tmp/test-non_utf8_path-\xff\xfe.map"));
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+    {
+        let _p = PersistentMap::create(path, 1024 * 1024, false, 0o666).unwrap();
+    }
+    let p = PersistentMap::open(path).unwrap();
+    assert_eq!(p.len(), 1024 * 1024);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn nul_in_path() {
+    let err = match PersistentMap::open("/tmp/test-nul\0in_path.map") {
+        Err(err) => err,
+        Ok(_) => panic!("Opened a path with a NUL byte"),
+    };
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.operation(), "pmem_map_file");
+    assert_eq!(err.raw_os_error(), None);
+}